@@ -0,0 +1,84 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use pt_invoice_automation::invoice::match_query::MatchQuery;
+use pt_invoice_automation::invoice::money::Money;
+use pt_invoice_automation::invoice::subset_problem::SubsetSolver;
+use pt_invoice_automation::invoice::subset_problem::exact_search::ExactSearchSolver;
+use pt_invoice_automation::invoice::subset_problem::greedy_search::GreedySearchSolver;
+use pt_invoice_automation::invoice::subset_problem::search_control::SearchControl;
+
+const INVOICE_COUNTS: [usize; 3] = [50, 200, 1000];
+const MAX_CANDIDATES: usize = 10;
+
+// Xorshift so every run benchmarks the same invoices without pulling in a random number crate
+struct Amounts(u64);
+
+impl Amounts {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        return self.0;
+    }
+}
+
+// Invoice totals between 1€ and 1000€, one in ten is a credit note, and a target paid by a few of them
+fn build_invoices(count: usize) -> (Vec<i64>, i64) {
+    let mut amounts = Amounts(0x5eed_0000 + count as u64);
+    let values: Vec<i64> = (0..count).map(|_| {
+        let cents = (amounts.next() % 99_900) as i64 + 100;
+        if amounts.next() % 10 == 0 { -cents / 4 } else { cents }
+    }).collect();
+    let target = (0..5).map(|_| values[(amounts.next() % count as u64) as usize]).sum();
+    return (values, target);
+}
+
+// The exact solver hands inputs too large for it to the greedy search, those runs are named after the search that really ran
+fn get_solver_label(name: &str, control: &SearchControl) -> String {
+    if control.is_approximate() {
+        return format!("{}_greedy_fallback", name);
+    }
+    return name.to_string();
+}
+
+fn bench_solve<S: SubsetSolver>(c: &mut Criterion, name: &str, solver: &S) {
+    let mut group = c.benchmark_group("solve");
+    group.sample_size(10);
+    for count in INVOICE_COUNTS {
+        let (values, target) = build_invoices(count);
+        let control = SearchControl::unbounded();
+        solver.solve_indices(&values, target, &control);
+        group.bench_with_input(BenchmarkId::new(get_solver_label(name, &control), count), &values, |b, values| {
+            b.iter(|| solver.solve_indices(black_box(values), black_box(target), &SearchControl::unbounded()));
+        });
+    }
+    group.finish();
+}
+
+fn bench_query_candidates<S: SubsetSolver>(c: &mut Criterion, name: &str, solver: &S) {
+    let mut group = c.benchmark_group("query_candidates");
+    group.sample_size(10);
+    for count in INVOICE_COUNTS {
+        let (values, target) = build_invoices(count);
+        let query = MatchQuery::new(Money::from_cents(target)).with_tolerance(Money::from_cents(100));
+        let control = SearchControl::unbounded();
+        solver.solve_query_candidates(&values, &query, MAX_CANDIDATES, &control, &mut |_, _| {});
+        group.bench_with_input(BenchmarkId::new(get_solver_label(name, &control), count), &values, |b, values| {
+            b.iter(|| solver.solve_query_candidates(black_box(values), &query, MAX_CANDIDATES, &SearchControl::unbounded(), &mut |_, _| {}));
+        });
+    }
+    group.finish();
+}
+
+fn greedy_benchmarks(c: &mut Criterion) {
+    bench_solve(c, "greedy", &GreedySearchSolver{});
+    bench_query_candidates(c, "greedy", &GreedySearchSolver{});
+}
+
+fn exact_benchmarks(c: &mut Criterion) {
+    bench_solve(c, "exact", &ExactSearchSolver{});
+    bench_query_candidates(c, "exact", &ExactSearchSolver{});
+}
+
+criterion_group!(benches, greedy_benchmarks, exact_benchmarks);
+criterion_main!(benches);
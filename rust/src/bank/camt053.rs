@@ -0,0 +1,153 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use roxmltree::Node;
+
+use super::{parse_amount, BankImportError, BankMovement};
+
+// Elements are matched by local name so any version of the camt.053 namespace is accepted
+fn find_child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    return node.children().find(|x| x.is_element() && x.tag_name().name() == name);
+}
+
+fn find_path<'a, 'input>(node: Node<'a, 'input>, path: &[&str]) -> Option<Node<'a, 'input>> {
+    let mut current = node;
+    for name in path {
+        current = find_child(current, name)?;
+    }
+    return Some(current);
+}
+
+fn get_path_text(node: Node, path: &[&str]) -> Option<String> {
+    return find_path(node, path).and_then(|x| x.text()).map(|x| x.trim().to_string()).filter(|x| !x.is_empty());
+}
+
+// Dates come either as Dt (2023-04-01) or DtTm (2023-04-01T10:00:00)
+fn get_entry_date(entry: Node, date_element: &str) -> Option<NaiveDate> {
+    if let Some(date) = get_path_text(entry, &[date_element, "Dt"]) {
+        return NaiveDate::parse_from_str(&date, "%Y-%m-%d").ok();
+    }
+    let date_time = get_path_text(entry, &[date_element, "DtTm"])?;
+    let date_time = date_time.get(..19)?;
+    return NaiveDateTime::parse_from_str(date_time, "%Y-%m-%dT%H:%M:%S").ok().map(|x| x.date());
+}
+
+fn get_entry_description(entry: Node) -> String {
+    let unstructured: Vec<String> = entry
+        .descendants()
+        .filter(|x| x.is_element() && x.tag_name().name() == "Ustrd")
+        .filter_map(|x| x.text())
+        .map(|x| x.trim().to_string())
+        .collect();
+    if !unstructured.is_empty() {
+        return unstructured.join(" ");
+    }
+    return get_path_text(entry, &["AddtlNtryInf"]).unwrap_or_default();
+}
+
+pub fn read_camt053_statement(text: &str) -> Result<Vec<BankMovement>, BankImportError> {
+    let document = roxmltree::Document::parse(text)?;
+
+    let mut movements = Vec::new();
+    let entries = document.descendants().filter(|x| x.is_element() && x.tag_name().name() == "Ntry");
+    for (idx, entry) in entries.enumerate() {
+        let malformed = |reason: &str| BankImportError::MalformedEntry { entry: idx + 1, reason: reason.to_string() };
+
+        let amount_text = get_path_text(entry, &["Amt"]).ok_or_else(|| malformed("missing Amt"))?;
+        let mut amount = parse_amount(&amount_text, false)?;
+        match get_path_text(entry, &["CdtDbtInd"]).as_deref() {
+            Some("DBIT") => amount = -amount,
+            Some("CRDT") => {},
+            _ => return Err(malformed("missing or invalid CdtDbtInd")),
+        }
+        // A reversal undoes a previous entry of the opposite direction
+        if get_path_text(entry, &["RvslInd"]).as_deref() == Some("true") {
+            amount = -amount;
+        }
+
+        let booking_date = get_entry_date(entry, "BookgDt")
+            .or_else(|| get_entry_date(entry, "ValDt"))
+            .ok_or_else(|| malformed("missing BookgDt"))?;
+        let reference = get_path_text(entry, &["AcctSvcrRef"])
+            .or_else(|| get_path_text(entry, &["NtryRef"]))
+            .unwrap_or_default();
+
+        movements.push(BankMovement {
+            booking_date: booking_date,
+            amount: amount,
+            description: get_entry_description(entry),
+            reference: reference,
+        });
+    }
+
+    return Ok(movements);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::invoice::money::Money;
+
+    const STATEMENT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Document xmlns="urn:iso:std:iso:20022:tech:xsd:camt.053.001.02">
+  <BkToCstmrStmt>
+    <Stmt>
+      <Ntry>
+        <NtryRef>1</NtryRef>
+        <Amt Ccy="EUR">1234.56</Amt>
+        <CdtDbtInd>DBIT</CdtDbtInd>
+        <BookgDt><Dt>2024-04-02</Dt></BookgDt>
+        <AcctSvcrRef>BANKREF1</AcctSvcrRef>
+        <NtryDtls><TxDtls><RmtInf><Ustrd>FT 2024/15</Ustrd><Ustrd>FORNECEDOR LDA</Ustrd></RmtInf></TxDtls></NtryDtls>
+      </Ntry>
+      <Ntry>
+        <NtryRef>2</NtryRef>
+        <Amt Ccy="EUR">99.90</Amt>
+        <CdtDbtInd>CRDT</CdtDbtInd>
+        <BookgDt><DtTm>2024-04-03T10:15:00+01:00</DtTm></BookgDt>
+        <AddtlNtryInf>DEP CLIENTE</AddtlNtryInf>
+      </Ntry>
+      <Ntry>
+        <Amt Ccy="EUR">10.00</Amt>
+        <CdtDbtInd>CRDT</CdtDbtInd>
+        <RvslInd>true</RvslInd>
+        <ValDt><Dt>2024-04-04</Dt></ValDt>
+      </Ntry>
+    </Stmt>
+  </BkToCstmrStmt>
+</Document>"#;
+
+    #[test]
+    fn parses_entries() {
+        let movements = read_camt053_statement(STATEMENT).unwrap();
+        assert_eq!(movements, vec![
+            BankMovement {
+                booking_date: NaiveDate::from_ymd_opt(2024, 4, 2).unwrap(),
+                amount: Money::from_cents(-123456),
+                description: "FT 2024/15 FORNECEDOR LDA".to_string(),
+                reference: "BANKREF1".to_string(),
+            },
+            BankMovement {
+                booking_date: NaiveDate::from_ymd_opt(2024, 4, 3).unwrap(),
+                amount: Money::from_cents(9990),
+                description: "DEP CLIENTE".to_string(),
+                reference: "2".to_string(),
+            },
+            BankMovement {
+                booking_date: NaiveDate::from_ymd_opt(2024, 4, 4).unwrap(),
+                amount: Money::from_cents(-1000),
+                description: String::new(),
+                reference: String::new(),
+            },
+        ]);
+    }
+
+    #[test]
+    fn rejects_entries_without_direction() {
+        let statement = "<Document><Ntry><Amt>1.00</Amt><BookgDt><Dt>2024-04-02</Dt></BookgDt></Ntry></Document>";
+        assert!(matches!(read_camt053_statement(statement), Err(BankImportError::MalformedEntry { entry: 1, .. })));
+    }
+
+    #[test]
+    fn rejects_invalid_xml() {
+        assert!(matches!(read_camt053_statement("<Document><Ntry>"), Err(BankImportError::Xml(_))));
+    }
+}
@@ -0,0 +1,235 @@
+use std::path::Path;
+
+use chrono::NaiveDate;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use super::{parse_amount, read_statement_file, BankImportError, BankMovement};
+use crate::invoice::money::Money;
+
+pub const BANK_CSV_CONFIG_JSON_PATH: &str = "bank_csv_config.json";
+
+// Layout of the CSV export of a bank, columns are given by header name or by zero based index
+// Either amount_column (signed) or debit_column and credit_column (both positive) must be set
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct CsvStatementConfig {
+    pub delimiter: char,
+    // Rows before the header, banks usually put the account details there
+    pub skip_lines: usize,
+    pub has_headers: bool,
+    pub date_column: String,
+    pub date_format: String,
+    pub amount_column: Option<String>,
+    pub debit_column: Option<String>,
+    pub credit_column: Option<String>,
+    pub description_column: String,
+    pub reference_column: Option<String>,
+    pub decimal_comma: bool,
+}
+
+impl Default for CsvStatementConfig {
+    fn default() -> Self {
+        Self {
+            delimiter: ';',
+            skip_lines: 0,
+            has_headers: true,
+            date_column: "Data Mov.".to_string(),
+            date_format: "%d-%m-%Y".to_string(),
+            amount_column: None,
+            debit_column: Some("Débito".to_string()),
+            credit_column: Some("Crédito".to_string()),
+            description_column: "Descrição".to_string(),
+            reference_column: None,
+            decimal_comma: true,
+        }
+    }
+}
+
+pub fn load_csv_config(path: &str) -> Result<CsvStatementConfig, BankImportError> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            info!("Bank CSV configuration {} not found, using the default layout", path);
+            return Ok(CsvStatementConfig::default());
+        },
+        Err(error) => return Err(error.into()),
+    };
+    return Ok(serde_json::from_str(&text)?);
+}
+
+fn find_column(headers: Option<&csv::StringRecord>, column: &str) -> Result<usize, BankImportError> {
+    if let Some(headers) = headers {
+        if let Some(idx) = headers.iter().position(|x| x.trim().eq_ignore_ascii_case(column.trim())) {
+            return Ok(idx);
+        }
+    }
+    return column.trim().parse::<usize>().map_err(|_| BankImportError::MissingColumn(column.to_string()));
+}
+
+fn find_optional_column(headers: Option<&csv::StringRecord>, column: &Option<String>) -> Result<Option<usize>, BankImportError> {
+    return column.as_ref().map(|x| find_column(headers, x)).transpose();
+}
+
+pub fn read_csv_statement(path: &Path, config: &CsvStatementConfig) -> Result<Vec<BankMovement>, BankImportError> {
+    let text = read_statement_file(path)?;
+    return parse_csv_statement(&text, config);
+}
+
+pub fn parse_csv_statement(text: &str, config: &CsvStatementConfig) -> Result<Vec<BankMovement>, BankImportError> {
+    // The reader splits on a single byte
+    if !config.delimiter.is_ascii() {
+        return Err(BankImportError::InvalidDelimiter(config.delimiter));
+    }
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(config.delimiter as u8)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    let mut records = reader.records();
+
+    // Skipped as records and not as lines, a quoted field may span several lines
+    for _ in 0..config.skip_lines {
+        if records.next().transpose()?.is_none() {
+            break;
+        }
+    }
+    let headers = if config.has_headers { records.next().transpose()? } else { None };
+    let date_idx = find_column(headers.as_ref(), &config.date_column)?;
+    let description_idx = find_column(headers.as_ref(), &config.description_column)?;
+    let reference_idx = find_optional_column(headers.as_ref(), &config.reference_column)?;
+    let amount_idx = find_optional_column(headers.as_ref(), &config.amount_column)?;
+    let debit_idx = find_optional_column(headers.as_ref(), &config.debit_column)?;
+    let credit_idx = find_optional_column(headers.as_ref(), &config.credit_column)?;
+    if amount_idx.is_none() && debit_idx.is_none() && credit_idx.is_none() {
+        return Err(BankImportError::MissingColumn("amount".to_string()));
+    }
+
+    let parse_optional_amount = |record: &csv::StringRecord, idx: Option<usize>| -> Result<Option<Money>, BankImportError> {
+        match idx.and_then(|x| record.get(x)).map(|x| x.trim()).filter(|x| !x.is_empty()) {
+            Some(text) => Ok(Some(parse_amount(text, config.decimal_comma)?)),
+            None => Ok(None),
+        }
+    };
+
+    let mut movements = Vec::new();
+    for (entry, record) in records.enumerate() {
+        let record = record?;
+        let date_text = record.get(date_idx).unwrap_or("").trim();
+        // Banks close the file with balance lines that have no movement date
+        if date_text.is_empty() {
+            continue;
+        }
+        let booking_date = match NaiveDate::parse_from_str(date_text, &config.date_format) {
+            Ok(date) => date,
+            Err(error) => {
+                warn!("Skipping statement line {} with invalid date {}: {}", entry + 1, date_text, error);
+                continue;
+            }
+        };
+
+        let amount = match parse_optional_amount(&record, amount_idx)? {
+            Some(amount) => amount,
+            None => {
+                let debit = parse_optional_amount(&record, debit_idx)?.unwrap_or(Money::ZERO);
+                let credit = parse_optional_amount(&record, credit_idx)?.unwrap_or(Money::ZERO);
+                credit.abs() - debit.abs()
+            }
+        };
+
+        movements.push(BankMovement {
+            booking_date: booking_date,
+            amount: amount,
+            description: record.get(description_idx).unwrap_or("").trim().to_string(),
+            reference: reference_idx.and_then(|x| record.get(x)).unwrap_or("").trim().to_string(),
+        });
+    }
+
+    return Ok(movements);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATEMENT: &str = "Conta;PT50 0000 0000 0000 0000 0000 0
+Data Mov.;Data Valor;Descrição;Débito;Crédito
+02-04-2024;02-04-2024;TRF FORNECEDOR LDA;1.234,56;
+03-04-2024;03-04-2024;DEP CLIENTE;;99,90
+;;Saldo final;;
+31-02-2024;31-02-2024;DATA INVALIDA;1,00;
+";
+
+    fn get_default_config() -> CsvStatementConfig {
+        return CsvStatementConfig { skip_lines: 1, ..CsvStatementConfig::default() };
+    }
+
+    #[test]
+    fn parses_debit_and_credit_columns() {
+        let movements = parse_csv_statement(STATEMENT, &get_default_config()).unwrap();
+        assert_eq!(movements, vec![
+            BankMovement {
+                booking_date: NaiveDate::from_ymd_opt(2024, 4, 2).unwrap(),
+                amount: Money::from_cents(-123456),
+                description: "TRF FORNECEDOR LDA".to_string(),
+                reference: String::new(),
+            },
+            BankMovement {
+                booking_date: NaiveDate::from_ymd_opt(2024, 4, 3).unwrap(),
+                amount: Money::from_cents(9990),
+                description: "DEP CLIENTE".to_string(),
+                reference: String::new(),
+            },
+        ]);
+    }
+
+    #[test]
+    fn parses_signed_amount_by_column_index() {
+        let config = CsvStatementConfig {
+            delimiter: ',',
+            has_headers: false,
+            date_column: "0".to_string(),
+            date_format: "%Y-%m-%d".to_string(),
+            amount_column: Some("2".to_string()),
+            debit_column: None,
+            credit_column: None,
+            description_column: "1".to_string(),
+            reference_column: Some("3".to_string()),
+            decimal_comma: false,
+            ..CsvStatementConfig::default()
+        };
+        let movements = parse_csv_statement("2024-04-05,Payment,-10.50,REF1\n2024-04-06,Refund,+3.00,REF2\n", &config).unwrap();
+        assert_eq!(movements.len(), 2);
+        assert_eq!(movements[0].amount, Money::from_cents(-1050));
+        assert_eq!(movements[0].reference, "REF1");
+        assert_eq!(movements[1].amount, Money::from_cents(300));
+        assert_eq!(movements[1].description, "Refund");
+    }
+
+    #[test]
+    fn keeps_quoted_fields_spanning_lines() {
+        let statement = "\"Conta\";\"PT50 0000\nBalcão Lisboa\"\nData Mov.;Descrição;Débito;Crédito\n02-04-2024;\"TRF FORNECEDOR\nFT 2024/15\";10,00;\n";
+        let movements = parse_csv_statement(statement, &get_default_config()).unwrap();
+        assert_eq!(movements.len(), 1);
+        assert_eq!(movements[0].description, "TRF FORNECEDOR\nFT 2024/15");
+        assert_eq!(movements[0].amount, Money::from_cents(-1000));
+    }
+
+    #[test]
+    fn rejects_non_ascii_delimiter() {
+        let config = CsvStatementConfig { delimiter: '§', ..CsvStatementConfig::default() };
+        assert!(matches!(parse_csv_statement(STATEMENT, &config), Err(BankImportError::InvalidDelimiter('§'))));
+    }
+
+    #[test]
+    fn reports_missing_columns() {
+        let config = CsvStatementConfig { description_column: "Movimento".to_string(), ..get_default_config() };
+        assert!(matches!(parse_csv_statement(STATEMENT, &config), Err(BankImportError::MissingColumn(column)) if column == "Movimento"));
+    }
+
+    #[test]
+    fn reports_invalid_amounts() {
+        let statement = "Data Mov.;Descrição;Débito;Crédito\n02-04-2024;TRF;abc;\n";
+        assert!(matches!(parse_csv_statement(statement, &CsvStatementConfig::default()), Err(BankImportError::InvalidAmount(_))));
+    }
+}
@@ -0,0 +1,154 @@
+use std::fmt;
+use std::path::Path;
+
+use chrono::NaiveDate;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+
+use crate::invoice::money::{Money, MoneyParsingError};
+
+pub mod camt053;
+pub mod csv_import;
+pub mod mt940;
+
+use csv_import::CsvStatementConfig;
+
+#[derive(thiserror::Error, Debug)]
+pub enum BankImportError {
+    #[error("Could not read the statement file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not read the CSV statement: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("Could not read the XML statement: {0}")]
+    Xml(#[from] roxmltree::Error),
+    #[error("Invalid CSV statement configuration: {0}")]
+    InvalidConfig(#[from] serde_json::Error),
+    #[error("CSV delimiter {0} is not an ASCII character")]
+    InvalidDelimiter(char),
+    #[error("Column {0} not found in the statement")]
+    MissingColumn(String),
+    #[error("Malformed entry {entry}: {reason}")]
+    MalformedEntry { entry: usize, reason: String },
+    #[error("Invalid amount {0}")]
+    InvalidAmount(#[from] MoneyParsingError),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum StatementFormat {
+    Csv,
+    Camt053,
+    Mt940,
+}
+
+impl StatementFormat {
+    pub const ALL: [StatementFormat; 3] = [StatementFormat::Csv, StatementFormat::Camt053, StatementFormat::Mt940];
+
+    // Guess based on the file extension, plain .txt files are left for the user to pick as they are not always MT940
+    pub fn from_path(path: &Path) -> Option<StatementFormat> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "csv" => Some(StatementFormat::Csv),
+            "xml" | "camt" => Some(StatementFormat::Camt053),
+            "sta" | "mt940" | "940" => Some(StatementFormat::Mt940),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for StatementFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StatementFormat::Csv => write!(f, "CSV"),
+            StatementFormat::Camt053 => write!(f, "CAMT.053"),
+            StatementFormat::Mt940 => write!(f, "MT940"),
+        }
+    }
+}
+
+// Single line of a bank statement, debits have a negative amount
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct BankMovement {
+    pub booking_date: NaiveDate,
+    pub amount: Money,
+    pub description: String,
+    pub reference: String,
+}
+
+impl BankMovement {
+    pub fn is_debit(&self) -> bool {
+        return self.amount.is_negative();
+    }
+}
+
+// Parses amounts written with a decimal comma or point, thousands separators and spaces are dropped
+pub fn parse_amount(text: &str, decimal_comma: bool) -> Result<Money, MoneyParsingError> {
+    let (thousands_separator, decimal_separator) = if decimal_comma { ('.', ',') } else { (',', '.') };
+    let normalized: String = text
+        .trim()
+        .chars()
+        .filter(|c| *c != thousands_separator && !c.is_whitespace() && *c != '+')
+        .map(|c| if c == decimal_separator { '.' } else { c })
+        .collect();
+    return normalized.parse();
+}
+
+// Windows-1252 characters for bytes 0x80 to 0x9F, the rest of the range is the same as ISO-8859-1
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8D}', 'Ž', '\u{8F}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9D}', 'ž', 'Ÿ',
+];
+
+// Portuguese banks still export statements in Windows-1252, files that are not valid UTF-8 are read as such
+pub fn decode_statement(bytes: Vec<u8>) -> String {
+    let bytes = match String::from_utf8(bytes) {
+        Ok(text) => return text.strip_prefix('\u{FEFF}').map(|x| x.to_string()).unwrap_or(text),
+        Err(error) => error.into_bytes(),
+    };
+    debug!("Statement is not UTF-8, reading it as Windows-1252");
+    return bytes.iter().map(|x| match x {
+        0x80..=0x9F => WINDOWS_1252_HIGH[(x - 0x80) as usize],
+        _ => *x as char,
+    }).collect();
+}
+
+pub fn read_statement_file(path: &Path) -> Result<String, BankImportError> {
+    return Ok(decode_statement(std::fs::read(path)?));
+}
+
+pub fn import_statement(path: &Path, format: StatementFormat, csv_config: &CsvStatementConfig) -> Result<Vec<BankMovement>, BankImportError> {
+    let movements = match format {
+        StatementFormat::Csv => csv_import::read_csv_statement(path, csv_config)?,
+        StatementFormat::Camt053 => camt053::read_camt053_statement(&read_statement_file(path)?)?,
+        StatementFormat::Mt940 => mt940::read_mt940_statement(&read_statement_file(path)?)?,
+    };
+    info!("Imported {} movements from {} statement {}", movements.len(), format, path.display());
+
+    return Ok(movements);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_utf8_statements_without_the_byte_order_mark() {
+        assert_eq!(decode_statement("\u{FEFF}Descrição".as_bytes().to_vec()), "Descrição");
+        assert_eq!(decode_statement("Crédito".as_bytes().to_vec()), "Crédito");
+    }
+
+    #[test]
+    fn reads_windows_1252_statements() {
+        // "Débito;Crédito €" as written by Windows-1252 exports
+        let bytes = vec![0x44, 0xE9, 0x62, 0x69, 0x74, 0x6F, 0x3B, 0x43, 0x72, 0xE9, 0x64, 0x69, 0x74, 0x6F, 0x20, 0x80];
+        assert_eq!(decode_statement(bytes), "Débito;Crédito €");
+    }
+
+    #[test]
+    fn guesses_format_from_extension() {
+        assert_eq!(StatementFormat::from_path(Path::new("extrato.CSV")), Some(StatementFormat::Csv));
+        assert_eq!(StatementFormat::from_path(Path::new("extrato.xml")), Some(StatementFormat::Camt053));
+        assert_eq!(StatementFormat::from_path(Path::new("extrato.sta")), Some(StatementFormat::Mt940));
+        assert_eq!(StatementFormat::from_path(Path::new("extrato.txt")), None);
+        assert_eq!(StatementFormat::from_path(Path::new("extrato")), None);
+    }
+}
@@ -0,0 +1,166 @@
+use chrono::{Datelike, NaiveDate};
+
+use super::{parse_amount, BankImportError, BankMovement};
+
+// Tag and value of a field, continuation lines are joined with new lines
+fn split_fields(text: &str) -> Vec<(String, String)> {
+    let mut fields: Vec<(String, String)> = Vec::new();
+
+    for line in text.lines() {
+        let line = line.trim_end();
+        // Block delimiters of the SWIFT envelope and message terminators, descriptions may have lines starting with -
+        if line.is_empty() || line.starts_with('{') || line == "-" || line.starts_with("-}") {
+            continue;
+        }
+
+        let tag_end = line.strip_prefix(':').and_then(|rest| rest.find(':')).map(|x| x + 1);
+        match tag_end {
+            Some(tag_end) if tag_end <= 4 => {
+                fields.push((line[1..tag_end].to_string(), line[tag_end + 1..].to_string()));
+            },
+            _ => {
+                if let Some((_, value)) = fields.last_mut() {
+                    value.push('\n');
+                    value.push_str(line);
+                }
+            }
+        }
+    }
+
+    return fields;
+}
+
+// The entry date only has month and day, its year is the one that puts it closest to the value date
+fn get_entry_date(value_date: NaiveDate, month: u32, day: u32) -> Option<NaiveDate> {
+    return [value_date.year() - 1, value_date.year(), value_date.year() + 1]
+        .into_iter()
+        .filter_map(|year| NaiveDate::from_ymd_opt(year, month, day))
+        .min_by_key(|x| (*x - value_date).num_days().abs());
+}
+
+// :61: YYMMDD[MMDD]{C|D|RC|RD}[funds code]amount{N|F|S}xxx reference[//bank reference]
+fn parse_statement_line(entry: usize, value: &str) -> Result<BankMovement, BankImportError> {
+    let malformed = |reason: &str| BankImportError::MalformedEntry { entry: entry, reason: reason.to_string() };
+    let line = value.lines().next().unwrap_or("");
+
+    let value_date = line.get(..6)
+        .and_then(|x| NaiveDate::parse_from_str(x, "%y%m%d").ok())
+        .ok_or_else(|| malformed("invalid value date"))?;
+    let mut rest = &line[6..];
+
+    let mut booking_date = value_date;
+    if rest.len() >= 4 && rest[..4].bytes().all(|c| c.is_ascii_digit()) {
+        let month = rest[..2].parse::<u32>().unwrap_or_default();
+        let day = rest[2..4].parse::<u32>().unwrap_or_default();
+        booking_date = get_entry_date(value_date, month, day).ok_or_else(|| malformed("invalid entry date"))?;
+        rest = &rest[4..];
+    }
+
+    // A reversal of a credit takes money out of the account
+    let (is_debit, mark_len) = if rest.starts_with("RC") {
+        (true, 2)
+    } else if rest.starts_with("RD") {
+        (false, 2)
+    } else if rest.starts_with('D') {
+        (true, 1)
+    } else if rest.starts_with('C') {
+        (false, 1)
+    } else {
+        return Err(malformed("missing debit or credit mark"));
+    };
+    rest = &rest[mark_len..];
+
+    // Third character of the currency code, only present on some statements
+    if rest.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        rest = &rest[1..];
+    }
+
+    let amount_len = rest.find(|c: char| !c.is_ascii_digit() && c != ',').unwrap_or(rest.len());
+    let amount = parse_amount(&rest[..amount_len], true)?;
+    rest = &rest[amount_len..];
+
+    // Transaction type identification, 1 letter and 3 characters
+    let reference = rest.get(4..).unwrap_or("");
+    let reference = reference.split("//").next().unwrap_or("").trim();
+
+    return Ok(BankMovement {
+        booking_date: booking_date,
+        amount: if is_debit { -amount } else { amount },
+        description: String::new(),
+        reference: reference.to_string(),
+    });
+}
+
+pub fn read_mt940_statement(text: &str) -> Result<Vec<BankMovement>, BankImportError> {
+    let mut movements: Vec<BankMovement> = Vec::new();
+    let mut last_tag = String::new();
+
+    for (tag, value) in split_fields(text) {
+        match tag.as_str() {
+            "61" => movements.push(parse_statement_line(movements.len() + 1, &value)?),
+            // Information to account owner, describes the statement line right before it
+            "86" if last_tag == "61" => {
+                if let Some(movement) = movements.last_mut() {
+                    movement.description = value.lines().map(|x| x.trim()).collect::<Vec<&str>>().join(" ");
+                }
+            },
+            _ => {},
+        }
+        last_tag = tag;
+    }
+
+    return Ok(movements);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::invoice::money::Money;
+
+    const STATEMENT: &str = "{1:F01BANKPTPLAXXX0000000000}{2:I940BANKPTPLXXXXN}{4:
+:20:STMT240402
+:25:PT50000000000000000000000
+:28C:1/1
+:60F:C240401EUR1000,00
+:61:2404020402D1234,56NTRFFT 2024-15//BANKREF1
+:86:TRF FORNECEDOR LDA
+FT 2024/15
+-15 DIAS
+:61:240403CR99,90NTRFNONREF
+:86:DEP CLIENTE
+:61:2312290102RC10,00NCHK123
+:62F:C240403EUR-134,66
+-}{5:{CHK:123456789ABC}}";
+
+    #[test]
+    fn parses_statement_lines() {
+        let movements = read_mt940_statement(STATEMENT).unwrap();
+        assert_eq!(movements, vec![
+            BankMovement {
+                booking_date: NaiveDate::from_ymd_opt(2024, 4, 2).unwrap(),
+                amount: Money::from_cents(-123456),
+                description: "TRF FORNECEDOR LDA FT 2024/15 -15 DIAS".to_string(),
+                reference: "FT 2024-15".to_string(),
+            },
+            BankMovement {
+                booking_date: NaiveDate::from_ymd_opt(2024, 4, 3).unwrap(),
+                amount: Money::from_cents(9990),
+                description: "DEP CLIENTE".to_string(),
+                reference: "NONREF".to_string(),
+            },
+            // Entry date in the year after the value date
+            BankMovement {
+                booking_date: NaiveDate::from_ymd_opt(2024, 1, 2).unwrap(),
+                amount: Money::from_cents(-1000),
+                description: String::new(),
+                reference: "123".to_string(),
+            },
+        ]);
+    }
+
+    #[test]
+    fn rejects_lines_without_debit_or_credit_mark() {
+        let statement = ":20:STMT\n:61:240402X10,00NTRFREF\n";
+        assert!(matches!(read_mt940_statement(statement), Err(BankImportError::MalformedEntry { entry: 1, .. })));
+    }
+}
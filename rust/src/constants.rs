@@ -1,5 +1,84 @@
-#[derive(Debug, PartialEq, Clone, Copy)]
+use std::fmt;
+use std::path::PathBuf;
+
+pub const DEFAULT_PDF_DPI: u32 = 200;
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum SourceType{
     Camera,
     Display,
+    // Single image or PDF, or every one of them in a directory, rescanned for new files when watch is set
+    // PDF pages without usable embedded images are rasterized at pdf_dpi
+    ImageFiles { path: PathBuf, watch: bool, pdf_dpi: u32 },
+    // Recorded session replayed through the pipeline
+    VideoFile { path: PathBuf, looping: bool, pacing: VideoPacing },
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum VideoPacing{
+    // Frames are never sent faster than the video was recorded
+    RealTime,
+    AsFastAsPossible,
+}
+
+// Controls of the video being replayed, ignored by other sources
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PlaybackCommand{
+    Pause,
+    Resume,
+    // Reads a single frame while paused
+    Step,
+    SetLooping(bool),
+    SetPacing(VideoPacing),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum RecordingFormat{
+    Video,
+    // One PNG per frame, lossless so detection can be tuned on the exact pixels
+    ImageSequence,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum RecordingCommand{
+    // Recording and its JSON lines sidecar are created inside the directory at path
+    Start { path: PathBuf, format: RecordingFormat },
+    Stop,
+}
+
+// Resolution, pixel format and frame rate of a camera, either one it accepts or the one it is using
+#[derive(Debug, PartialEq, Clone)]
+pub struct CameraMode{
+    pub width: i32,
+    pub height: i32,
+    pub fourcc: String,
+    pub fps: f64,
+}
+
+impl fmt::Display for CameraMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{} {} {:.0} fps", self.width, self.height, self.fourcc, self.fps)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct CameraInfo{
+    pub index: i32,
+    pub modes: Vec<CameraMode>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum CameraCommand{
+    // Probes every other device in the background, the camera in use keeps running and is listed with its current mode
+    Enumerate,
+    // No mode keeps the camera default preferences
+    Select { index: i32, mode: Option<CameraMode> },
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum CameraReport{
+    Devices(Vec<CameraInfo>),
+    // Settings read back from the device, which may differ from the requested ones
+    Opened { index: i32, settings: CameraMode },
+    Failed(String),
 }
\ No newline at end of file
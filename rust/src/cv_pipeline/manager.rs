@@ -6,6 +6,7 @@ use std::time::Instant;
 use log::{debug};
 
 use crate::cv_pipeline::{SourceStage,Stage};
+use crate::qr_code::QRCodeOrigin;
 
 
 //It uses internal mutability pattern
@@ -30,6 +31,20 @@ impl CVPipelineManager{
         self.start_stage = Some(source);
     }
 
+    pub fn has_source(&self) -> bool {
+        return self.start_stage.is_some();
+    }
+
+    pub fn get_source_origin(&self) -> Option<QRCodeOrigin> {
+        return self.start_stage.as_ref().and_then(|x| x.borrow().get_frame_origin());
+    }
+
+    pub fn notify_source_qr_found(&mut self) {
+        if let Some(ref mut start_stage) = self.start_stage {
+            start_stage.borrow_mut().on_qr_found();
+        }
+    }
+
     pub fn add_stage(&mut self, stage: Rc<RefCell<dyn Stage>>){
         self.stages.push(stage);
     }
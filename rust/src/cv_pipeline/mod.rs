@@ -1,5 +1,6 @@
 use opencv::{prelude::*};
 use anyhow::{Result};
+use crate::qr_code::QRCodeOrigin;
 
 pub mod manager;
 pub mod stages;
@@ -10,5 +11,16 @@ pub trait Stage {
 
 pub trait SourceStage {
     fn get_frame(&mut self) -> Result<Box<Mat>>;
+    // Where the last frame came from, for sources reading files
+    fn get_frame_origin(&self) -> Option<QRCodeOrigin> {
+        return None;
+    }
+    // Called when the last frame had QR codes, sources holding several views of the same page can skip the rest
+    fn on_qr_found(&mut self) {}
     fn get_name(&self) -> &str;
-}
\ No newline at end of file
+}
+
+// Returned by sources with nothing to give right now, like a directory whose images were all read
+#[derive(thiserror::Error, Debug)]
+#[error("Source {0} has no new frames")]
+pub struct NoFrameAvailable(pub String);
@@ -1,40 +1,203 @@
 use opencv::{prelude::*, videoio};
 use anyhow::{Result, anyhow};
 use crate::cv_pipeline::SourceStage;
+use crate::constants::{CameraInfo, CameraMode};
 const DEFAULT_CAMERA_INDEX: i32 = 0;
-use log::{info, debug};
+// OpenCV cannot list devices, enumerating tries every index below this one
+const MAX_CAMERA_INDEX: i32 = 8;
+// Common modes asked to each device, the ones it gives back unchanged are the ones it supports
+const PROBE_RESOLUTIONS: [(i32, i32); 6] = [(640, 480), (800, 600), (1280, 720), (1600, 1200), (1920, 1080), (3840, 2160)];
+const PROBE_FOURCCS: [&str; 2] = ["MJPG", "YUYV"];
+const PREFERRED_FPS: f64 = 30.0;
+use log::{info, debug, warn};
 
 pub struct OpenCVCameraSource{
     camera: videoio::VideoCapture,
+    index: i32,
+    // Requested mode, None asks for the preferred one
+    mode: Option<CameraMode>,
+    // Applied again whenever the device is reopened
+    focus: Option<u8>,
 }
 
 impl OpenCVCameraSource{
-    pub fn new(idx : Option<i32>) -> Result<Self> {
+    pub fn new(idx : Option<i32>, mode: Option<CameraMode>) -> Result<Self> {
         let camera_index = idx.unwrap_or(DEFAULT_CAMERA_INDEX);
-        debug!("Opening camera {}", camera_index);
-        let mut camera = videoio::VideoCapture::new(camera_index, videoio::CAP_ANY)?;
+        let camera = OpenCVCameraSource::open_camera(camera_index, mode.as_ref())?;
         debug!("Camera has been created");
+
+        let source = Self {
+            camera: camera,
+            index: camera_index,
+            mode: mode,
+            focus: None,
+        };
+        source.log_settings()?;
+
+        Ok(source)
+    }
+
+    // Sharp invoices need a large image, MJPG is what most webcams use to give one at full frame rate
+    fn get_preferred_mode() -> CameraMode {
+        return CameraMode { width: 1920, height: 1080, fourcc: "MJPG".to_string(), fps: PREFERRED_FPS };
+    }
+
+    fn get_fourcc_code(fourcc: &str) -> Result<i32> {
+        let chars: Vec<char> = fourcc.chars().collect();
+        if chars.len() != 4 {
+            return Err(anyhow!("Invalid pixel format {}", fourcc));
+        }
+        return Ok(videoio::VideoWriter::fourcc(chars[0], chars[1], chars[2], chars[3])?);
+    }
+
+    fn get_fourcc_name(code: f64) -> String {
+        return (code as u32).to_le_bytes().iter()
+            .map(|x| *x as char)
+            .collect::<String>()
+            .trim_end_matches('\0')
+            .to_string();
+    }
+
+    fn open_device(camera_index: i32) -> Result<videoio::VideoCapture> {
+        debug!("Opening camera {}", camera_index);
+        let camera = videoio::VideoCapture::new(camera_index, videoio::CAP_ANY)?;
         let opened = videoio::VideoCapture::is_opened(&camera)?;
         if !opened {
             return Err(anyhow!("Could not open camera {}", camera_index));
         }
+        return Ok(camera);
+    }
 
-        let fourcc = videoio::VideoWriter::fourcc('M', 'J', 'P', 'G')?; 
-
-        camera.set(videoio::CAP_PROP_FOURCC, fourcc as f64)?;
-        camera.set(videoio::CAP_PROP_FRAME_WIDTH, 1920.0)?;
-        camera.set(videoio::CAP_PROP_FRAME_HEIGHT, 1080.0)?;
+    fn open_camera(camera_index: i32, mode: Option<&CameraMode>) -> Result<videoio::VideoCapture> {
+        let mut camera = OpenCVCameraSource::open_device(camera_index)?;
+        match mode {
+            Some(mode) => OpenCVCameraSource::request_mode(&mut camera, mode)?,
+            None => OpenCVCameraSource::request_mode(&mut camera, &OpenCVCameraSource::get_preferred_mode())?,
+        }
         camera.set(videoio::CAP_PROP_AUTOFOCUS, 0.0)?;
-        camera.set(videoio::CAP_PROP_FPS, 30.0)?;
-        info!("Camera index({}) opened with resolution {}x{}", camera_index, 1920, 1080);
-   
-        Ok(Self {
-            camera: camera
-        })
+        return Ok(camera);
+    }
+
+    // The format goes first, some drivers only offer the large resolutions compressed
+    fn request_mode(camera: &mut videoio::VideoCapture, mode: &CameraMode) -> Result<()> {
+        camera.set(videoio::CAP_PROP_FOURCC, OpenCVCameraSource::get_fourcc_code(&mode.fourcc)? as f64)?;
+        camera.set(videoio::CAP_PROP_FRAME_WIDTH, mode.width as f64)?;
+        camera.set(videoio::CAP_PROP_FRAME_HEIGHT, mode.height as f64)?;
+        camera.set(videoio::CAP_PROP_FPS, mode.fps)?;
+        return Ok(());
+    }
+
+    // Devices silently fall back to the closest mode they have, so what is in use can only be read back
+    fn read_mode(camera: &videoio::VideoCapture) -> Result<CameraMode> {
+        return Ok(CameraMode {
+            width: camera.get(videoio::CAP_PROP_FRAME_WIDTH)? as i32,
+            height: camera.get(videoio::CAP_PROP_FRAME_HEIGHT)? as i32,
+            fourcc: OpenCVCameraSource::get_fourcc_name(camera.get(videoio::CAP_PROP_FOURCC)?),
+            fps: camera.get(videoio::CAP_PROP_FPS)?,
+        });
+    }
+
+    fn probe_modes(camera_index: i32) -> Result<Vec<CameraMode>> {
+        let mut camera = OpenCVCameraSource::open_device(camera_index)?;
+        let mut modes = Vec::new();
+        for fourcc in PROBE_FOURCCS {
+            for (width, height) in PROBE_RESOLUTIONS {
+                let requested = CameraMode { width: width, height: height, fourcc: fourcc.to_string(), fps: PREFERRED_FPS };
+                OpenCVCameraSource::request_mode(&mut camera, &requested)?;
+                let mode = OpenCVCameraSource::read_mode(&camera)?;
+                if mode.width == width && mode.height == height && mode.fourcc == fourcc && !modes.contains(&mode) {
+                    modes.push(mode);
+                }
+            }
+        }
+        // Backends that cannot change the mode still have the one they started with
+        if modes.is_empty() {
+            modes.push(OpenCVCameraSource::read_mode(&camera)?);
+        }
+        camera.release()?;
+        debug!("Camera {} supports {:?}", camera_index, modes);
+        return Ok(modes);
+    }
+
+    // Devices in use cannot be opened again, the camera in use is listed as given instead of probed
+    pub fn enumerate(in_use: Option<CameraInfo>) -> Vec<CameraInfo> {
+        let mut cameras = Vec::new();
+        for index in 0..MAX_CAMERA_INDEX {
+            if let Some(camera) = in_use.as_ref().filter(|x| x.index == index) {
+                cameras.push(camera.clone());
+                continue;
+            }
+            match OpenCVCameraSource::probe_modes(index) {
+                Ok(modes) => cameras.push(CameraInfo { index: index, modes: modes }),
+                Err(error) => debug!("No usable camera at index {}: {}", index, error),
+            }
+        }
+        info!("Found {} cameras", cameras.len());
+        return cameras;
+    }
+
+    fn log_settings(&self) -> Result<CameraMode> {
+        let settings = self.get_settings()?;
+        info!("Camera index({}) opened with {}", self.index, settings);
+        if let Some(mode) = &self.mode {
+            if *mode != settings {
+                warn!("Camera index({}) was asked for {} but uses {}", self.index, mode, settings);
+            }
+        }
+        return Ok(settings);
+    }
+
+    pub fn get_settings(&self) -> Result<CameraMode> {
+        return OpenCVCameraSource::read_mode(&self.camera);
+    }
+
+    pub fn get_index(&self) -> i32 {
+        return self.index;
+    }
+
+    pub fn is_opened(&self) -> bool {
+        return self.camera.is_opened().unwrap_or(false);
+    }
+
+    pub fn release(&mut self) -> Result<()> {
+        self.camera.release()?;
+        return Ok(());
+    }
+
+    pub fn reopen(&mut self) -> Result<()> {
+        self.camera = OpenCVCameraSource::open_camera(self.index, self.mode.as_ref())?;
+        if let Some(focus) = self.focus {
+            self.set_focus(focus)?;
+        }
+        return Ok(());
+    }
+
+    // The camera in use is released first, as it may not open twice, and reopened if the new one fails
+    pub fn select(&mut self, index: i32, mode: Option<CameraMode>) -> Result<CameraMode> {
+        self.camera.release()?;
+        match OpenCVCameraSource::open_camera(index, mode.as_ref()) {
+            Ok(camera) => {
+                self.camera = camera;
+                self.index = index;
+                self.mode = mode;
+            },
+            Err(error) => {
+                // The caller needs to know why the new camera failed, not why the old one did not come back
+                if let Err(reopen_error) = self.reopen() {
+                    warn!("Could not reopen camera {}: {}", self.index, reopen_error);
+                }
+                return Err(error);
+            }
+        }
+        if let Some(focus) = self.focus {
+            self.set_focus(focus)?;
+        }
+        return self.log_settings();
     }
 
     pub fn set_focus(&mut self, focus: u8) -> Result<()>{
         self.camera.set(videoio::CAP_PROP_FOCUS, focus as f64)?;
+        self.focus = Some(focus);
         info!("Camera focus set to {}", focus);
         return Ok(());
     }
@@ -0,0 +1,289 @@
+use opencv::{prelude::*, core, imgcodecs, imgproc, videoio};
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use log::{info, debug, warn};
+use crate::constants::RecordingFormat;
+use crate::cv_pipeline::Stage;
+use crate::qr_code::QRCode;
+
+// Frame rate used when the recording is too short to time its frames
+const DEFAULT_RECORDING_FPS: f64 = 10.0;
+// Frames timed before the video is created, its header gets the rate the pipeline really runs at
+const FRAME_RATE_SAMPLE_FRAMES: usize = 10;
+const VIDEO_EXTENSION: &str = "mp4";
+// Sidecar is flushed every few frames so a crash at a customer site still leaves the labels of most of the recording
+const SIDECAR_FLUSH_INTERVAL: u64 = 30;
+
+#[derive(Serialize)]
+struct RecordedDetection {
+    data: String,
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+}
+
+#[derive(Serialize)]
+struct RecordedFrame {
+    index: u64,
+    // Image of the frame, for image sequences only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file: Option<String>,
+    elapsed_ms: u64,
+    detections: Vec<RecordedDetection>,
+}
+
+// First line of the sidecar, each frame follows on a line of its own
+#[derive(Serialize)]
+struct RecordingHeader {
+    started_at: DateTime<Local>,
+    // Video file, or directory holding the image sequence
+    recording: String,
+    fps: f64,
+    width: i32,
+    height: i32,
+}
+
+struct Recording {
+    format: RecordingFormat,
+    output_path: PathBuf,
+    sidecar_path: PathBuf,
+    sidecar: BufWriter<File>,
+    header: RecordingHeader,
+    // Created once the frame rate is measured, with the size of the first frame
+    writer: Option<videoio::VideoWriter>,
+    frame_size: Option<core::Size>,
+    started: Instant,
+    // None while the first frames are timed
+    fps: Option<f64>,
+    // Video frames kept until the writer exists
+    sampled_frames: Vec<Mat>,
+    // Frames not in the sidecar yet, the last one still gets the detections of the decoder
+    pending_frames: Vec<RecordedFrame>,
+    frame_count: u64,
+}
+
+// Writes the frames it receives, before any detection draws on them, to a video or a numbered image sequence
+// QR codes found on each frame are added by the worker and saved to a JSON lines sidecar, labelling the recording for tuning detection
+pub struct FrameRecorderStage {
+    recording: Option<Recording>,
+}
+
+impl FrameRecorderStage {
+    pub fn new() -> Self {
+        Self {
+            recording: None
+        }
+    }
+
+    pub fn start(&mut self, directory: &Path, format: RecordingFormat) -> Result<()> {
+        if self.recording.is_some() {
+            self.stop()?;
+        }
+        if !directory.is_dir() {
+            return Err(anyhow!("Recording directory {} does not exist", directory.display()));
+        }
+
+        let started_at = Local::now();
+        let name = format!("recording_{}", started_at.format("%Y%m%d_%H%M%S"));
+        let output_path = match format {
+            RecordingFormat::Video => directory.join(format!("{}.{}", name, VIDEO_EXTENSION)),
+            RecordingFormat::ImageSequence => {
+                let frames_directory = directory.join(&name);
+                std::fs::create_dir_all(&frames_directory)?;
+                frames_directory
+            },
+        };
+        let sidecar_path = directory.join(format!("{}.jsonl", name));
+        let sidecar = BufWriter::new(File::create(&sidecar_path)?);
+
+        self.recording = Some(Recording {
+            format: format,
+            output_path: output_path.clone(),
+            sidecar_path: sidecar_path,
+            sidecar: sidecar,
+            header: RecordingHeader {
+                started_at: started_at,
+                recording: output_path.to_string_lossy().to_string(),
+                fps: DEFAULT_RECORDING_FPS,
+                width: 0,
+                height: 0,
+            },
+            writer: None,
+            frame_size: None,
+            started: Instant::now(),
+            fps: None,
+            sampled_frames: Vec::new(),
+            pending_frames: Vec::new(),
+            frame_count: 0,
+        });
+        info!("Recording {:?} to {}", format, output_path.display());
+        return Ok(());
+    }
+
+    pub fn stop(&mut self) -> Result<()> {
+        if let Some(mut recording) = self.recording.take() {
+            if recording.fps.is_none() {
+                FrameRecorderStage::begin_output(&mut recording)?;
+            }
+            FrameRecorderStage::write_pending_frames(&mut recording)?;
+            recording.sidecar.flush()?;
+            if let Some(writer) = recording.writer.as_mut() {
+                writer.release()?;
+            }
+            info!("Recording {} stopped after {} frames", recording.output_path.display(), recording.frame_count);
+        }
+        return Ok(());
+    }
+
+    // QR codes decoded from the last recorded frame
+    pub fn add_detections(&mut self, qr_codes: &[Box<QRCode>]) {
+        let frame = match self.recording.as_mut().and_then(|x| x.pending_frames.last_mut()) {
+            Some(frame) => frame,
+            None => return,
+        };
+        for qr_code in qr_codes.iter() {
+            let rect = qr_code.get_rect();
+            frame.detections.push(RecordedDetection {
+                data: qr_code.get_data().clone(),
+                x: rect.x,
+                y: rect.y,
+                width: rect.width,
+                height: rect.height,
+            });
+        }
+    }
+
+    // Frames per second between the first and the last frame timed
+    fn measure_fps(frames: &[RecordedFrame]) -> f64 {
+        let duration_ms = match (frames.first(), frames.last()) {
+            (Some(first), Some(last)) => last.elapsed_ms - first.elapsed_ms,
+            _ => 0,
+        };
+        if duration_ms == 0 {
+            return DEFAULT_RECORDING_FPS;
+        }
+        return (frames.len() - 1) as f64 * 1000.0 / duration_ms as f64;
+    }
+
+    // Writes the sidecar header and creates the video once the frame rate is known
+    fn begin_output(recording: &mut Recording) -> Result<()> {
+        let fps = FrameRecorderStage::measure_fps(&recording.pending_frames);
+        recording.fps = Some(fps);
+        recording.header.fps = fps;
+        if let Some(frame_size) = recording.frame_size {
+            recording.header.width = frame_size.width;
+            recording.header.height = frame_size.height;
+        }
+        serde_json::to_writer(&mut recording.sidecar, &recording.header)?;
+        recording.sidecar.write_all(b"\n")?;
+        info!("Recording {} at {:.1} FPS", recording.output_path.display(), fps);
+
+        let frame_size = match recording.frame_size {
+            Some(frame_size) if recording.format == RecordingFormat::Video => frame_size,
+            _ => return Ok(()),
+        };
+        let fourcc = videoio::VideoWriter::fourcc('m', 'p', '4', 'v')?;
+        let mut writer = videoio::VideoWriter::new(&recording.output_path.to_string_lossy(), fourcc, fps, frame_size, true)?;
+        if !writer.is_opened()? {
+            return Err(anyhow!("Could not open video writer for {}", recording.output_path.display()));
+        }
+        for frame in recording.sampled_frames.drain(..) {
+            FrameRecorderStage::write_video_frame(&mut writer, frame_size, &frame)?;
+        }
+        recording.writer = Some(writer);
+        return Ok(());
+    }
+
+    fn write_pending_frames(recording: &mut Recording) -> Result<()> {
+        for frame in recording.pending_frames.drain(..) {
+            serde_json::to_writer(&mut recording.sidecar, &frame)?;
+            recording.sidecar.write_all(b"\n")?;
+        }
+        if recording.frame_count % SIDECAR_FLUSH_INTERVAL == 0 {
+            recording.sidecar.flush()?;
+            debug!("Flushed recording sidecar {}", recording.sidecar_path.display());
+        }
+        return Ok(());
+    }
+
+    // A video has a single size, frames from sources like image files are scaled to the first one
+    fn write_video_frame(writer: &mut videoio::VideoWriter, frame_size: core::Size, input: &Mat) -> Result<()> {
+        if input.size()? != frame_size {
+            let mut resized = Mat::default();
+            imgproc::resize(input, &mut resized, frame_size, 0.0, 0.0, imgproc::INTER_LINEAR)?;
+            writer.write(&resized)?;
+        } else {
+            writer.write(input)?;
+        }
+        return Ok(());
+    }
+
+    fn write_frame(recording: &mut Recording, input: &Mat) -> Result<Option<String>> {
+        let frame_size = *recording.frame_size.get_or_insert(input.size()?);
+
+        match recording.format {
+            RecordingFormat::Video => {
+                match recording.writer.as_mut() {
+                    Some(writer) => FrameRecorderStage::write_video_frame(writer, frame_size, input)?,
+                    None => recording.sampled_frames.push(input.try_clone()?),
+                }
+                return Ok(None);
+            },
+            RecordingFormat::ImageSequence => {
+                let file_name = format!("frame_{:06}.png", recording.frame_count);
+                let file_path = recording.output_path.join(&file_name);
+                if !imgcodecs::imwrite(&file_path.to_string_lossy(), input, &core::Vector::new())? {
+                    return Err(anyhow!("Could not write frame {}", file_path.display()));
+                }
+                return Ok(Some(file_name));
+            },
+        }
+    }
+
+    fn record_frame(recording: &mut Recording, input: &Mat) -> Result<()> {
+        let file = FrameRecorderStage::write_frame(recording, input)?;
+        // Detections of the previous frame were added by now
+        if recording.fps.is_some() {
+            FrameRecorderStage::write_pending_frames(recording)?;
+        }
+        recording.pending_frames.push(RecordedFrame {
+            index: recording.frame_count,
+            file: file,
+            elapsed_ms: recording.started.elapsed().as_millis() as u64,
+            detections: Vec::new(),
+        });
+        recording.frame_count += 1;
+
+        if recording.fps.is_none() && recording.pending_frames.len() >= FRAME_RATE_SAMPLE_FRAMES {
+            FrameRecorderStage::begin_output(recording)?;
+        }
+        return Ok(());
+    }
+}
+
+impl Stage for FrameRecorderStage {
+    fn process(&mut self, input: &mut Mat) -> Result<()>{
+        let recording = match self.recording.as_mut() {
+            Some(recording) => recording,
+            None => return Ok(()),
+        };
+
+        // A failing disk must not stop the detection, the recording is dropped instead
+        if let Err(error) = FrameRecorderStage::record_frame(recording, input) {
+            warn!("Stopping recording {}: {}", recording.output_path.display(), error);
+            return self.stop();
+        }
+
+        return Ok(());
+    }
+
+    fn get_name(&self) -> &str{
+        return "FrameRecorderStage";
+    }
+}
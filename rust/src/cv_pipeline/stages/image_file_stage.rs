@@ -0,0 +1,156 @@
+use opencv::{prelude::*, imgcodecs};
+use anyhow::{Result, anyhow};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use log::{info, debug};
+use crate::cv_pipeline::{NoFrameAvailable, SourceStage};
+use crate::qr_code::QRCodeOrigin;
+use super::pdf_file_stage::PdfFileSource;
+
+const IMAGE_EXTENSIONS: [&str; 6] = ["jpg", "jpeg", "png", "tif", "tiff", "bmp"];
+const PDF_EXTENSION: &str = "pdf";
+// Time between two listings of a watched directory
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+// Scanned invoices read from a single image or PDF or from every one of them in a directory, in name order
+pub struct ImageFileSource {
+    path: PathBuf,
+    watch: bool,
+    pdf_dpi: u32,
+    pending_files: VecDeque<PathBuf>,
+    // Files already queued, so a watched directory only sends the new ones
+    known_files: HashSet<PathBuf>,
+    // Size and modification time of new files in a watched directory, they are queued once a listing sees them unchanged
+    growing_files: HashMap<PathBuf, (u64, Option<SystemTime>)>,
+    current_file: Option<PathBuf>,
+    // PDF being read, its pages are sent before moving to the next file
+    current_pdf: Option<PdfFileSource>,
+    last_scan: Instant,
+}
+
+impl ImageFileSource {
+    pub fn new(path: &Path, watch: bool, pdf_dpi: u32) -> Result<Self> {
+        if !path.exists() {
+            return Err(anyhow!("Image source {} does not exist", path.display()));
+        }
+
+        let mut source = Self {
+            path: path.to_path_buf(),
+            watch: watch && path.is_dir(),
+            pdf_dpi: pdf_dpi,
+            pending_files: VecDeque::new(),
+            known_files: HashSet::new(),
+            growing_files: HashMap::new(),
+            current_file: None,
+            current_pdf: None,
+            last_scan: Instant::now(),
+        };
+        source.scan_files(false)?;
+        info!("Image source {} opened with {} files{}", path.display(), source.pending_files.len(), if source.watch { ", watching for new files" } else { "" });
+
+        Ok(source)
+    }
+
+    fn get_extension(path: &Path) -> Option<String> {
+        return path.extension().and_then(|x| x.to_str()).map(|x| x.to_lowercase());
+    }
+
+    fn is_supported_file(path: &Path) -> bool {
+        return path.is_file() && ImageFileSource::get_extension(path)
+            .map_or(false, |x| x == PDF_EXTENSION || IMAGE_EXTENSIONS.contains(&x.as_str()));
+    }
+
+    // A scanner may still be writing a file when the listing sees it, reading it then would fail once and never again
+    fn is_settled(&mut self, file: &Path) -> bool {
+        let metadata = match std::fs::metadata(file) {
+            Ok(metadata) => metadata,
+            Err(_) => return false,
+        };
+        let state = (metadata.len(), metadata.modified().ok());
+        if self.growing_files.get(file) == Some(&state) {
+            self.growing_files.remove(file);
+            return true;
+        }
+        self.growing_files.insert(file.to_path_buf(), state);
+        return false;
+    }
+
+    fn scan_files(&mut self, wait_for_writes: bool) -> Result<()> {
+        self.last_scan = Instant::now();
+
+        let mut files: Vec<PathBuf> = if self.path.is_dir() {
+            std::fs::read_dir(&self.path)?
+                .filter_map(|entry| entry.ok().map(|x| x.path()))
+                .filter(|x| ImageFileSource::is_supported_file(x))
+                .collect()
+        } else {
+            vec![self.path.clone()]
+        };
+        files.sort();
+
+        for file in files {
+            if self.known_files.contains(&file) || (wait_for_writes && !self.is_settled(&file)) {
+                continue;
+            }
+            debug!("Queued image file {}", file.display());
+            self.known_files.insert(file.clone());
+            self.pending_files.push_back(file);
+        }
+        return Ok(());
+    }
+}
+
+impl SourceStage for ImageFileSource {
+    fn get_frame(&mut self) -> Result<Box<Mat>> {
+        if let Some(pdf) = self.current_pdf.as_mut() {
+            if !pdf.is_finished() {
+                return pdf.get_frame();
+            }
+            self.current_pdf = None;
+        }
+
+        if self.pending_files.is_empty() && self.watch && self.last_scan.elapsed() >= WATCH_INTERVAL {
+            self.scan_files(true)?;
+        }
+
+        let file = match self.pending_files.pop_front() {
+            Some(file) => file,
+            None => return Err(NoFrameAvailable(self.get_name().to_string()).into()),
+        };
+        self.current_file = Some(file.clone());
+
+        if ImageFileSource::get_extension(&file).as_deref() == Some(PDF_EXTENSION) {
+            let mut pdf = PdfFileSource::new(&file, self.pdf_dpi)?;
+            let frame = pdf.get_frame();
+            self.current_pdf = Some(pdf);
+            return frame;
+        }
+
+        // imread gives an empty matrix instead of an error for unreadable files
+        let frame = imgcodecs::imread(&file.to_string_lossy(), imgcodecs::IMREAD_COLOR)?;
+        if frame.empty() {
+            return Err(anyhow!("Could not decode image {}", file.display()));
+        }
+        info!("Reading image file {}", file.display());
+
+        return Ok(Box::new(frame));
+    }
+
+    fn get_frame_origin(&self) -> Option<QRCodeOrigin> {
+        if let Some(pdf) = &self.current_pdf {
+            return pdf.get_frame_origin();
+        }
+        return self.current_file.as_ref().map(|x| QRCodeOrigin{file: x.clone(), page: None, frame: None});
+    }
+
+    fn on_qr_found(&mut self) {
+        if let Some(pdf) = self.current_pdf.as_mut() {
+            pdf.on_qr_found();
+        }
+    }
+
+    fn get_name(&self) -> &str{
+        return "ImageFileSource";
+    }
+}
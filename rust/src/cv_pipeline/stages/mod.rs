@@ -2,4 +2,8 @@ pub mod camera_stage;
 pub mod qr_detect_stage;
 pub mod egui_dispatcher_stage;
 pub mod wechat_qr_detect_stage;
-pub mod display_recorder_stage;
\ No newline at end of file
+pub mod display_recorder_stage;
+pub mod image_file_stage;
+pub mod pdf_file_stage;
+pub mod video_file_stage;
+pub mod frame_recorder_stage;
\ No newline at end of file
@@ -0,0 +1,217 @@
+use opencv::{prelude::*, core, imgcodecs, imgproc};
+use anyhow::{Result, anyhow};
+use lopdf::{Dictionary, Document, Object};
+use lopdf::xobject::PdfImage;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+use log::{info, debug, warn, error};
+use crate::cv_pipeline::{NoFrameAvailable, SourceStage};
+use crate::qr_code::QRCodeOrigin;
+
+// Poppler tool, the same the Python parser uses through pdf2image
+const PDFTOPPM_COMMAND: &str = "pdftoppm";
+// Whether pdftoppm could be run, checked once per run of the program
+static PDFTOPPM_AVAILABLE: OnceLock<bool> = OnceLock::new();
+// Logos and icons are not worth a detection pass
+const MIN_EMBEDDED_IMAGE_SIZE: i64 = 64;
+
+// Pages of a PDF in order, the images embedded in each page are tried before rasterizing it
+// Scanned invoices are usually one image per page, which is sharper than any rasterization
+pub struct PdfFileSource {
+    path: PathBuf,
+    dpi: u32,
+    document: Document,
+    page_numbers: VecDeque<u32>,
+    // Frames left for the current page, the rasterized page is always the last one when pdftoppm is available
+    pending_frames: VecDeque<PdfFrame>,
+    current_page: Option<u32>,
+}
+
+enum PdfFrame {
+    Embedded(Mat),
+    Rasterized(u32),
+}
+
+impl PdfFileSource {
+    pub fn new(path: &Path, dpi: u32) -> Result<Self> {
+        let document = Document::load(path).map_err(|error| anyhow!("Could not open PDF {}: {}", path.display(), error))?;
+        let page_numbers: VecDeque<u32> = document.get_pages().keys().copied().collect();
+        info!("PDF source {} opened with {} pages at {} DPI", path.display(), page_numbers.len(), dpi);
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            dpi: dpi,
+            document: document,
+            page_numbers: page_numbers,
+            pending_frames: VecDeque::new(),
+            current_page: None,
+        })
+    }
+
+    pub fn is_finished(&self) -> bool {
+        return self.page_numbers.is_empty() && self.pending_frames.is_empty();
+    }
+
+    fn queue_next_page(&mut self) -> bool {
+        let page_number = match self.page_numbers.pop_front() {
+            Some(page_number) => page_number,
+            None => return false,
+        };
+        self.current_page = Some(page_number);
+
+        let page_id = match self.document.get_pages().get(&page_number) {
+            Some(page_id) => *page_id,
+            None => return false,
+        };
+        // Pages without image resources make lopdf fail, they can only be rasterized
+        let images = self.document.get_page_images(page_id).unwrap_or_default();
+        for image in images.iter() {
+            match self.decode_embedded_image(image) {
+                Ok(Some(frame)) => self.pending_frames.push_back(PdfFrame::Embedded(frame)),
+                Ok(None) => debug!("Skipping embedded image {:?} on page {} of {}", image.filters, page_number, self.path.display()),
+                Err(error) => warn!("Could not decode embedded image on page {} of {}: {}", page_number, self.path.display(), error),
+            }
+        }
+        debug!("Page {} of {} has {} usable embedded images", page_number, self.path.display(), self.pending_frames.len());
+        if PdfFileSource::is_pdftoppm_available() {
+            self.pending_frames.push_back(PdfFrame::Rasterized(page_number));
+        }
+
+        return true;
+    }
+
+    // JPEG and JPEG 2000 streams are decoded as they are, 8 bit RGB or gray ones are inflated into a matrix
+    // Other encodings, like the CCITT and JBIG2 of black and white scans, are left to the rasterization
+    fn decode_embedded_image(&self, image: &PdfImage) -> Result<Option<Mat>> {
+        if image.width < MIN_EMBEDDED_IMAGE_SIZE || image.height < MIN_EMBEDDED_IMAGE_SIZE {
+            return Ok(None);
+        }
+
+        let filters = image.filters.clone().unwrap_or_default();
+        match filters.last().map(|x| x.as_str()) {
+            Some("DCTDecode") | Some("JPXDecode") if filters.len() == 1 => {
+                let data = core::Vector::<u8>::from_slice(image.content);
+                let frame = imgcodecs::imdecode(&data, imgcodecs::IMREAD_COLOR)?;
+                return Ok(if frame.empty() { None } else { Some(frame) });
+            },
+            Some("FlateDecode") | None => {},
+            _ => return Ok(None),
+        }
+
+        let (channels, mat_type, conversion) = match image.color_space.as_deref() {
+            Some("DeviceRGB") => (3, core::CV_8UC3, imgproc::COLOR_RGB2BGR),
+            Some("DeviceGray") => (1, core::CV_8UC1, imgproc::COLOR_GRAY2BGR),
+            _ => return Ok(None),
+        };
+        if image.bits_per_component != Some(8) {
+            return Ok(None);
+        }
+
+        // lopdf refuses to inflate image streams, a copy without the subtype is handled as any other stream
+        let mut stream = self.document.get_object(image.id)?.as_stream()?.clone();
+        if self.has_predictor(&stream.dict) {
+            return Ok(None);
+        }
+        stream.dict.remove(b"Subtype");
+        let pixels = stream.get_plain_content()?;
+        if pixels.len() != (image.width * image.height * channels) as usize {
+            return Err(anyhow!("Image has {} bytes for {}x{} pixels", pixels.len(), image.width, image.height));
+        }
+
+        let mut frame = Mat::default();
+        unsafe{
+            let pixels_mat = Mat::new_rows_cols_with_data(image.height as i32, image.width as i32, mat_type, pixels.as_ptr() as *mut std::ffi::c_void, 0)?;
+            imgproc::cvt_color(&pixels_mat, &mut frame, conversion, 0)?;
+        }
+        return Ok(Some(frame));
+    }
+
+    // PNG and TIFF predictors, common on scanner output, are left to the rasterization as lopdf only undoes some of them
+    fn has_predictor(&self, dict: &Dictionary) -> bool {
+        let parms = match dict.get(b"DecodeParms").and_then(|x| self.document.dereference(x)) {
+            Ok((_, parms)) => parms,
+            Err(_) => return false,
+        };
+        // One entry per filter when there are several
+        let parms: Vec<&Object> = match parms.as_array() {
+            Ok(parms) => parms.iter().collect(),
+            Err(_) => vec![parms],
+        };
+        return parms.into_iter()
+            .filter_map(|x| self.document.dereference(x).ok().and_then(|(_, x)| x.as_dict().ok()))
+            .any(|x| x.get(b"Predictor").and_then(Object::as_i64).unwrap_or(1) > 1);
+    }
+
+    // Without poppler only the embedded images can be read, that is reported once instead of on every page
+    fn is_pdftoppm_available() -> bool {
+        return *PDFTOPPM_AVAILABLE.get_or_init(|| {
+            match Command::new(PDFTOPPM_COMMAND).arg("-v").output() {
+                Ok(_) => true,
+                Err(error) => {
+                    error!("Could not run {}, PDF pages will not be rasterized and only their embedded images will be read. Install poppler to read every page: {}", PDFTOPPM_COMMAND, error);
+                    false
+                }
+            }
+        });
+    }
+
+    fn rasterize_page(&self, page_number: u32) -> Result<Mat> {
+        let output = Command::new(PDFTOPPM_COMMAND)
+            .arg("-r").arg(self.dpi.to_string())
+            .arg("-f").arg(page_number.to_string())
+            .arg("-l").arg(page_number.to_string())
+            .arg("-singlefile")
+            .arg("-png")
+            .arg(&self.path)
+            .output()
+            .map_err(|error| anyhow!("Could not run {}, is poppler installed? {}", PDFTOPPM_COMMAND, error))?;
+        if !output.status.success() {
+            return Err(anyhow!("{} failed on page {} of {}: {}", PDFTOPPM_COMMAND, page_number, self.path.display(), String::from_utf8_lossy(&output.stderr).trim()));
+        }
+
+        let data = core::Vector::<u8>::from_slice(&output.stdout);
+        let frame = imgcodecs::imdecode(&data, imgcodecs::IMREAD_COLOR)?;
+        if frame.empty() {
+            return Err(anyhow!("Could not decode page {} of {}", page_number, self.path.display()));
+        }
+        return Ok(frame);
+    }
+}
+
+impl SourceStage for PdfFileSource {
+    fn get_frame(&mut self) -> Result<Box<Mat>> {
+        if self.pending_frames.is_empty() && !self.queue_next_page() {
+            return Err(NoFrameAvailable(self.get_name().to_string()).into());
+        }
+
+        match self.pending_frames.pop_front() {
+            Some(PdfFrame::Embedded(frame)) => {
+                debug!("Reading embedded image of page {:?} of {}", self.current_page, self.path.display());
+                return Ok(Box::new(frame));
+            },
+            Some(PdfFrame::Rasterized(page_number)) => {
+                info!("Rasterizing page {} of {}", page_number, self.path.display());
+                return Ok(Box::new(self.rasterize_page(page_number)?));
+            },
+            None => return Err(NoFrameAvailable(self.get_name().to_string()).into()),
+        }
+    }
+
+    // The QR code of the page was already read from one of its images, no need to rasterize it
+    fn on_qr_found(&mut self) {
+        if !self.pending_frames.is_empty() {
+            debug!("Skipping the rest of page {:?} of {}", self.current_page, self.path.display());
+            self.pending_frames.clear();
+        }
+    }
+
+    fn get_frame_origin(&self) -> Option<QRCodeOrigin> {
+        return Some(QRCodeOrigin{file: self.path.clone(), page: self.current_page, frame: None});
+    }
+
+    fn get_name(&self) -> &str{
+        return "PdfFileSource";
+    }
+}
@@ -0,0 +1,148 @@
+use opencv::{prelude::*, videoio};
+use anyhow::{Result, anyhow};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use log::{info, debug};
+use crate::constants::{PlaybackCommand, VideoPacing};
+use crate::cv_pipeline::{NoFrameAvailable, SourceStage};
+use crate::qr_code::QRCodeOrigin;
+
+// Recorded scanning session read frame by frame, used to replay detection failures
+pub struct VideoFileSource {
+    path: PathBuf,
+    capture: videoio::VideoCapture,
+    looping: bool,
+    pacing: VideoPacing,
+    paused: bool,
+    // Frames requested while paused
+    pending_steps: u32,
+    // Time between frames when the video was recorded, None when the file does not tell
+    frame_interval: Option<Duration>,
+    last_frame_at: Option<Instant>,
+    // Index of the last frame read, None before the first one
+    frame_index: Option<u64>,
+    finished: bool,
+}
+
+impl VideoFileSource {
+    pub fn new(path: &Path, looping: bool, pacing: VideoPacing) -> Result<Self> {
+        let capture = videoio::VideoCapture::from_file(&path.to_string_lossy(), videoio::CAP_ANY)?;
+        let opened = videoio::VideoCapture::is_opened(&capture)?;
+        if !opened {
+            return Err(anyhow!("Could not open video {}", path.display()));
+        }
+
+        let fps = capture.get(videoio::CAP_PROP_FPS)?;
+        let frame_count = capture.get(videoio::CAP_PROP_FRAME_COUNT)?;
+        info!("Video {} opened with {} frames at {} FPS", path.display(), frame_count, fps);
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            capture: capture,
+            looping: looping,
+            pacing: pacing,
+            paused: false,
+            pending_steps: 0,
+            frame_interval: if fps > 0.0 { Some(Duration::from_secs_f64(1.0 / fps)) } else { None },
+            last_frame_at: None,
+            frame_index: None,
+            finished: false,
+        })
+    }
+
+    pub fn apply_command(&mut self, command: PlaybackCommand) {
+        match command {
+            PlaybackCommand::Pause => self.paused = true,
+            PlaybackCommand::Resume => {
+                self.paused = false;
+                self.pending_steps = 0;
+            },
+            PlaybackCommand::Step => {
+                if self.paused {
+                    self.pending_steps += 1;
+                }
+            },
+            PlaybackCommand::SetLooping(looping) => {
+                self.looping = looping;
+                // Looping again after reaching the end starts over
+                if looping && self.finished {
+                    self.finished = false;
+                }
+            },
+            PlaybackCommand::SetPacing(pacing) => self.pacing = pacing,
+        }
+        debug!("Applied playback command {:?} to {}", command, self.path.display());
+    }
+
+    fn wait_frame_interval(&self) {
+        if self.pacing != VideoPacing::RealTime || self.pending_steps > 0 {
+            return;
+        }
+        if let (Some(frame_interval), Some(last_frame_at)) = (self.frame_interval, self.last_frame_at) {
+            let elapsed = last_frame_at.elapsed();
+            if elapsed < frame_interval {
+                std::thread::sleep(frame_interval - elapsed);
+            }
+        }
+    }
+
+    fn read_frame(&mut self) -> Result<Option<Mat>> {
+        let mut frame = Mat::default();
+        let read = self.capture.read(&mut frame)?;
+        if !read || frame.empty() {
+            return Ok(None);
+        }
+        return Ok(Some(frame));
+    }
+
+    fn rewind(&mut self) -> Result<()> {
+        self.capture.set(videoio::CAP_PROP_POS_FRAMES, 0.0)?;
+        self.frame_index = None;
+        debug!("Rewinding video {}", self.path.display());
+        return Ok(());
+    }
+}
+
+impl SourceStage for VideoFileSource {
+    fn get_frame(&mut self) -> Result<Box<Mat>> {
+        if (self.paused && self.pending_steps == 0) || (self.finished && !self.looping) {
+            return Err(NoFrameAvailable(self.get_name().to_string()).into());
+        }
+        if self.finished {
+            self.rewind()?;
+            self.finished = false;
+        }
+
+        self.wait_frame_interval();
+        let mut frame = self.read_frame()?;
+        if frame.is_none() && self.looping {
+            self.rewind()?;
+            frame = self.read_frame()?;
+        }
+        let frame = match frame {
+            Some(frame) => frame,
+            None => {
+                info!("Reached the end of video {}", self.path.display());
+                self.finished = true;
+                return Err(NoFrameAvailable(self.get_name().to_string()).into());
+            }
+        };
+
+        self.last_frame_at = Some(Instant::now());
+        self.frame_index = Some(self.frame_index.map_or(0, |x| x + 1));
+        if self.pending_steps > 0 {
+            self.pending_steps -= 1;
+            debug!("Stepped to frame {:?} of {}", self.frame_index, self.path.display());
+        }
+
+        return Ok(Box::new(frame));
+    }
+
+    fn get_frame_origin(&self) -> Option<QRCodeOrigin> {
+        return Some(QRCodeOrigin{file: self.path.clone(), page: None, frame: self.frame_index});
+    }
+
+    fn get_name(&self) -> &str{
+        return "VideoFileSource";
+    }
+}
@@ -2,25 +2,47 @@ use super::cv_pipeline::manager::CVPipelineManager;
 use super::cv_pipeline::stages::camera_stage::OpenCVCameraSource;
 use super::cv_pipeline::stages::display_recorder_stage::DisplaySource;
 use super::cv_pipeline::stages::egui_dispatcher_stage::BGRConvertToEguiStage;
+use super::cv_pipeline::stages::frame_recorder_stage::FrameRecorderStage;
+use super::cv_pipeline::stages::image_file_stage::ImageFileSource;
+use super::cv_pipeline::stages::video_file_stage::VideoFileSource;
+use super::cv_pipeline::NoFrameAvailable;
 use super::cv_pipeline::stages::wechat_qr_detect_stage::WeChatQRCodeDecoderStage;
 use super::qr_code;
 use std::cell::RefCell;
 use std::sync::mpsc;
 use std::rc::{Rc};
-use log::{info, warn};
-use super::constants::SourceType;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use anyhow::Result;
+use log::{debug, info, warn};
+use super::constants::{SourceType, PlaybackCommand, RecordingCommand, CameraCommand, CameraReport, CameraInfo, CameraMode};
+
+// Wait before asking again a source that had no frame, so an idle file source does not spin
+const IDLE_SOURCE_SLEEP: Duration = Duration::from_millis(200);
+// Wait after a failed frame, so a camera that went away does not spin the worker and flood the log
+const FAILED_FRAME_SLEEP: Duration = Duration::from_millis(500);
 
 
 // TODO: Replace refcell with lifetimes
 pub struct CVWorker{
 
     display_source : Rc<RefCell<DisplaySource>>,
-    camera_source : Rc<RefCell<OpenCVCameraSource>>,    
+    // None until a camera opens, the worker starts without one on machines that have none
+    camera_source : Option<Rc<RefCell<OpenCVCameraSource>>>,
+    // Enumeration runs on its own thread, probing every device takes seconds
+    camera_probe : Option<JoinHandle<()>>,
     egui_img_converter : Rc<RefCell<BGRConvertToEguiStage>>,
     qr_decoder_stage : Rc<RefCell<WeChatQRCodeDecoderStage>>,
+    frame_recorder : Rc<RefCell<FrameRecorderStage>>,
+    // Kept to forward the playback controls while a video is the source
+    video_source : Option<Rc<RefCell<VideoFileSource>>>,
 
     rx_source : mpsc::Receiver<SourceType>,
     rx_camera_focus : mpsc::Receiver<u8>,
+    rx_playback : mpsc::Receiver<PlaybackCommand>,
+    rx_recording : mpsc::Receiver<RecordingCommand>,
+    rx_camera : mpsc::Receiver<CameraCommand>,
+    tx_camera_report : mpsc::Sender<CameraReport>,
     tx_img : mpsc::Sender<Box<egui::ColorImage>>,
     tx_qr : mpsc::Sender<Box<qr_code::QRCode>>,
 
@@ -29,32 +51,81 @@ pub struct CVWorker{
 
 
 impl CVWorker{
-    pub fn create_pipeline(tx_img : mpsc::Sender<Box<egui::ColorImage>>, tx_qr : mpsc::Sender<Box<qr_code::QRCode>>, rx_focus : mpsc::Receiver<u8>, rx_source : mpsc::Receiver<SourceType>) -> Self {
+    pub fn create_pipeline(tx_img : mpsc::Sender<Box<egui::ColorImage>>, tx_qr : mpsc::Sender<Box<qr_code::QRCode>>, rx_focus : mpsc::Receiver<u8>, rx_source : mpsc::Receiver<SourceType>, rx_playback : mpsc::Receiver<PlaybackCommand>, rx_recording : mpsc::Receiver<RecordingCommand>, rx_camera : mpsc::Receiver<CameraCommand>, tx_camera_report : mpsc::Sender<CameraReport>) -> Self {
         let mut pipeline_manager = CVPipelineManager::new();
 
         let rc_source_display = Rc::new(RefCell::new(DisplaySource::primary().unwrap()));
-        let rc_source_camera = Rc::new(RefCell::new(OpenCVCameraSource::new(Some(0)).unwrap()));
         let rc_egui_img_converter = Rc::new(RefCell::new(BGRConvertToEguiStage::new()));
         let rc_qr_decoder_stage = Rc::new(RefCell::new(WeChatQRCodeDecoderStage::new()));
+        let rc_frame_recorder = Rc::new(RefCell::new(FrameRecorderStage::new()));
         //let mut qr_decoder_stage = Box::new(QRCodeDecoderStage::new());
         
         info!("Pipelines stages have been created");
-        pipeline_manager.set_source(rc_source_camera.clone());
+        // Recorder goes first so it saves the frames before the decoder draws on them
+        pipeline_manager.add_stage(rc_frame_recorder.clone());
         pipeline_manager.add_stage(rc_qr_decoder_stage.clone());
         pipeline_manager.add_stage(rc_egui_img_converter.clone());
     
         
-        Self {
+        let mut worker = Self {
             pipeline : pipeline_manager,
-            camera_source : rc_source_camera,
+            camera_source : None,
+            camera_probe : None,
             egui_img_converter : rc_egui_img_converter,
             qr_decoder_stage : rc_qr_decoder_stage,
+            frame_recorder : rc_frame_recorder,
             display_source : rc_source_display,
+            video_source : None,
 
             rx_camera_focus : rx_focus,
             rx_source : rx_source,
+            rx_playback : rx_playback,
+            rx_recording : rx_recording,
+            rx_camera : rx_camera,
+            tx_camera_report : tx_camera_report,
             tx_img : tx_img,
             tx_qr : tx_qr,
+        };
+
+        let report = worker.open_camera(None, None);
+        worker.send_camera_report(report);
+        if let Some(camera_source) = &worker.camera_source {
+            worker.pipeline.set_source(camera_source.clone());
+        }
+        return worker;
+    }
+
+    // Replaces the camera in use, or opens one when there is none yet
+    fn open_camera(&mut self, index: Option<i32>, mode: Option<CameraMode>) -> CameraReport {
+        let result = match self.camera_source.clone() {
+            Some(camera_source) => {
+                let index = index.unwrap_or(camera_source.borrow().get_index());
+                camera_source.borrow_mut().select(index, mode)
+            },
+            None => OpenCVCameraSource::new(index, mode).and_then(|camera_source| {
+                let settings = camera_source.get_settings()?;
+                self.camera_source = Some(Rc::new(RefCell::new(camera_source)));
+                return Ok(settings);
+            }),
+        };
+        return self.get_camera_report(result);
+    }
+
+    fn get_camera_report(&self, result: Result<CameraMode>) -> CameraReport {
+        match (result, &self.camera_source) {
+            (Ok(settings), Some(camera_source)) => CameraReport::Opened { index: camera_source.borrow().get_index(), settings: settings },
+            (Ok(_), None) => CameraReport::Failed("No camera open".to_string()),
+            (Err(error), _) => {
+                warn!("Could not open camera: {}", error);
+                CameraReport::Failed(error.to_string())
+            },
+        }
+    }
+
+    // The UI may already be gone while the worker is shutting down
+    fn send_camera_report(&self, report: CameraReport) {
+        if let Err(error) = self.tx_camera_report.send(report) {
+            warn!("Could not send camera report: {}", error);
         }
     }
 
@@ -62,10 +133,46 @@ impl CVWorker{
         if let Ok(source) = self.rx_source.try_recv(){
             match source {
                 SourceType::Camera => {
-                    self.pipeline.set_source(self.camera_source.clone());
+                    // A camera plugged in after startup is opened when it is picked
+                    if self.camera_source.is_none() {
+                        let report = self.open_camera(None, None);
+                        self.send_camera_report(report);
+                    }
+                    let camera_source = match &self.camera_source {
+                        Some(camera_source) => camera_source.clone(),
+                        None => return,
+                    };
+                    self.video_source = None;
+                    self.pipeline.set_source(camera_source);
                 },
                 SourceType::Display => {
+                    self.video_source = None;
                     self.pipeline.set_source(self.display_source.clone());
+                },
+                SourceType::ImageFiles { path, watch, pdf_dpi } => {
+                    match ImageFileSource::new(&path, watch, pdf_dpi) {
+                        Ok(image_source) => {
+                            self.video_source = None;
+                            self.pipeline.set_source(Rc::new(RefCell::new(image_source)));
+                        },
+                        Err(error) => {
+                            warn!("Could not open image source: {}", error);
+                            return;
+                        }
+                    }
+                },
+                SourceType::VideoFile { path, looping, pacing } => {
+                    match VideoFileSource::new(&path, looping, pacing) {
+                        Ok(video_source) => {
+                            let rc_video_source = Rc::new(RefCell::new(video_source));
+                            self.pipeline.set_source(rc_video_source.clone());
+                            self.video_source = Some(rc_video_source);
+                        },
+                        Err(error) => {
+                            warn!("Could not open video source: {}", error);
+                            return;
+                        }
+                    }
                 }
             }
             info!("Source has been changed");
@@ -75,10 +182,76 @@ impl CVWorker{
     fn handle_change_focus(&mut self){
         let focus = self.rx_camera_focus.try_iter().last();
         if let Some(focus) = focus {
-            self.camera_source.borrow_mut().set_focus(focus).unwrap();
+            let camera_source = match &self.camera_source {
+                Some(camera_source) => camera_source,
+                None => {
+                    warn!("Ignoring camera focus {}, no camera is open", focus);
+                    return;
+                }
+            };
+            // The camera may be closed after a failed selection
+            if let Err(error) = camera_source.borrow_mut().set_focus(focus) {
+                warn!("Could not set camera focus: {}", error);
+            }
         }
     }
 
+    fn handle_playback(&mut self){
+        for command in self.rx_playback.try_iter() {
+            match &self.video_source {
+                Some(video_source) => video_source.borrow_mut().apply_command(command),
+                None => warn!("Ignoring playback command {:?}, the source is not a video", command),
+            }
+        }
+    }
+
+    fn handle_recording(&mut self){
+        if let Ok(command) = self.rx_recording.try_recv(){
+            let result = match command {
+                RecordingCommand::Start { path, format } => self.frame_recorder.borrow_mut().start(&path, format),
+                RecordingCommand::Stop => self.frame_recorder.borrow_mut().stop(),
+            };
+            if let Err(error) = result {
+                warn!("Could not change recording: {}", error);
+            }
+        }
+    }
+
+    fn handle_camera(&mut self){
+        if let Ok(command) = self.rx_camera.try_recv(){
+            match command {
+                CameraCommand::Enumerate => self.enumerate_cameras(),
+                CameraCommand::Select { index, mode } => {
+                    let report = self.open_camera(Some(index), mode);
+                    if let (CameraReport::Opened { .. }, Some(camera_source)) = (&report, &self.camera_source) {
+                        self.video_source = None;
+                        self.pipeline.set_source(camera_source.clone());
+                    }
+                    self.send_camera_report(report);
+                }
+            }
+        }
+    }
+
+    // Probes the devices on another thread so the live camera keeps running, the camera in use is listed with its current mode
+    fn enumerate_cameras(&mut self){
+        if self.camera_probe.as_ref().map_or(false, |x| !x.is_finished()) {
+            debug!("Camera enumeration already running");
+            return;
+        }
+        let in_use = self.camera_source.as_ref()
+            .map(|x| x.borrow())
+            .filter(|x| x.is_opened())
+            .and_then(|x| x.get_settings().ok().map(|settings| CameraInfo { index: x.get_index(), modes: vec![settings] }));
+        let tx_camera_report = self.tx_camera_report.clone();
+        self.camera_probe = Some(std::thread::spawn(move || {
+            let cameras = OpenCVCameraSource::enumerate(in_use);
+            if let Err(error) = tx_camera_report.send(CameraReport::Devices(cameras)) {
+                warn!("Could not send camera report: {}", error);
+            }
+        }));
+    }
+
     fn handle_new_image(&mut self){
         
         let img = self.egui_img_converter.borrow_mut().pop_last_image();
@@ -90,7 +263,14 @@ impl CVWorker{
     fn handle_new_qr(&mut self){
         let qr = self.qr_decoder_stage.borrow_mut().pop_last_qrs();
         if let Some(qr_vec) = qr {
-            for qr in qr_vec {
+            self.pipeline.notify_source_qr_found();
+            self.frame_recorder.borrow_mut().add_detections(&qr_vec);
+            let origin = self.pipeline.get_source_origin();
+            for mut qr in qr_vec {
+                if let Some(origin) = &origin {
+                    info!("Decoded QR code from {}", origin);
+                }
+                qr.set_origin(origin.clone());
                 self.tx_qr.send(qr).unwrap();
             }
         }
@@ -99,16 +279,24 @@ impl CVWorker{
     fn handle_channels(&mut self){
         self.handle_change_source();
         self.handle_change_focus();
+        self.handle_playback();
+        self.handle_recording();
+        self.handle_camera();
         self.handle_new_image();
         self.handle_new_qr();
     }
 
     pub fn run(&mut self){
         loop {
-            if let Ok(frame) = self.pipeline.process(){
-                self.handle_channels();
-            }else{
-                warn!("Fail to process frame, skipping frame");
+            match self.pipeline.process(){
+                Ok(_) => {},
+                // Nothing to read until a camera opens or another source is picked
+                Err(_) if !self.pipeline.has_source() => std::thread::sleep(IDLE_SOURCE_SLEEP),
+                Err(error) if error.is::<NoFrameAvailable>() => std::thread::sleep(IDLE_SOURCE_SLEEP),
+                Err(error) => {
+                    warn!("Fail to process frame, skipping frame: {}", error);
+                    std::thread::sleep(FAILED_FRAME_SLEEP);
+                },
             }
             self.handle_channels();
         }
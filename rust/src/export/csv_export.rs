@@ -0,0 +1,18 @@
+use std::path::Path;
+
+use super::{ExportError, ExportValue};
+
+// Semicolon is the list separator Excel expects with Portuguese regional settings
+pub const CSV_DELIMITER: u8 = b';';
+
+pub fn write_csv(path: &Path, headers: &[String], rows: &[Vec<ExportValue>]) -> Result<(), ExportError> {
+    let mut writer = csv::WriterBuilder::new().delimiter(CSV_DELIMITER).from_path(path)?;
+
+    writer.write_record(headers)?;
+    for row in rows {
+        writer.write_record(row.iter().map(|x| x.to_text()))?;
+    }
+    writer.flush()?;
+
+    return Ok(());
+}
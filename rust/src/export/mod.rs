@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+
+use chrono::NaiveDate;
+use log::info;
+
+use crate::invoice::{Invoice, InvoiceMappingTable};
+use crate::invoice::invoice_qr::QR_FIELD_CODES;
+use crate::invoice::money::Money;
+
+pub mod csv_export;
+pub mod xlsx_export;
+
+pub const COLUMN_HEADERS_JSON_PATH: &str = "column_headers.json";
+pub const EXPORT_DIRECTORY: &str = "exports";
+
+// Extra columns that are not part of the QR code
+pub const SUPPLIER_COLUMN: &str = "SUPPLIER";
+pub const WARNINGS_COLUMN: &str = "WARNINGS";
+
+// Maps a column code (QR field letter or one of the extra columns) to its header in the export language
+pub type ColumnHeaderTable = HashMap<String, String>;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ExportError {
+    #[error("Could not write the export file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not write the CSV file: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("Could not write the XLSX file: {0}")]
+    Xlsx(#[from] rust_xlsxwriter::XlsxError),
+    #[error("Invalid column header file: {0}")]
+    InvalidColumnHeaders(#[from] serde_json::Error),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ExportFormat {
+    Csv,
+    Xlsx,
+}
+
+impl ExportFormat {
+    pub fn get_extension(&self) -> &str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Xlsx => "xlsx",
+        }
+    }
+}
+
+// Typed cell value so each writer can decide how to represent it
+#[derive(Debug, PartialEq, Clone)]
+pub enum ExportValue {
+    Empty,
+    Text(String),
+    Amount(Money),
+    Integer(u32),
+    Date(NaiveDate),
+}
+
+impl ExportValue {
+    pub fn to_text(&self) -> String {
+        match self {
+            ExportValue::Empty => String::new(),
+            ExportValue::Text(text) => text.clone(),
+            ExportValue::Amount(amount) => amount.to_string(),
+            ExportValue::Integer(value) => value.to_string(),
+            ExportValue::Date(date) => date.format("%Y-%m-%d").to_string(),
+        }
+    }
+}
+
+impl From<Option<Money>> for ExportValue {
+    fn from(value: Option<Money>) -> Self {
+        value.map_or(ExportValue::Empty, ExportValue::Amount)
+    }
+}
+
+pub fn get_export_columns() -> Vec<&'static str> {
+    let mut columns = QR_FIELD_CODES.to_vec();
+    columns.push(SUPPLIER_COLUMN);
+    columns.push(WARNINGS_COLUMN);
+    return columns;
+}
+
+pub fn get_column_header(column_headers: &ColumnHeaderTable, column: &str) -> String {
+    return column_headers.get(column).cloned().unwrap_or(column.to_string());
+}
+
+// Value of a VAT block field (I, J or K), looked up by its letter as an I1:0 document may still have a J or K block
+fn get_vat_column_value(invoice: &dyn Invoice, prefix: char, idx: u8) -> ExportValue {
+    let fields = invoice.get_fields();
+    let breakdown = match fields.get_vat_block(prefix) {
+        Some(breakdown) => breakdown,
+        // Documents without VAT still have I1 set to 0
+        None if prefix == 'I' && idx == 1 => return ExportValue::Text("0".to_string()),
+        None => return ExportValue::Empty,
+    };
+
+    match idx {
+        1 => ExportValue::Text(breakdown.fiscal_space.to_string()),
+        2 => breakdown.exempt.map(|x| x.taxable_base).into(),
+        3 => breakdown.reduced.map(|x| x.taxable_base).into(),
+        4 => breakdown.reduced.map(|x| x.vat).into(),
+        5 => breakdown.intermediate.map(|x| x.taxable_base).into(),
+        6 => breakdown.intermediate.map(|x| x.vat).into(),
+        7 => breakdown.normal.map(|x| x.taxable_base).into(),
+        8 => breakdown.normal.map(|x| x.vat).into(),
+        _ => ExportValue::Empty,
+    }
+}
+
+pub fn get_column_value(invoice: &dyn Invoice, column: &str, supplier_names: &InvoiceMappingTable) -> ExportValue {
+    let fields = invoice.get_fields();
+
+    match column {
+        "A" => ExportValue::Text(fields.issuer_nif.clone()),
+        "B" => ExportValue::Text(fields.acquirer_nif.clone()),
+        "C" => ExportValue::Text(fields.acquirer_country.clone()),
+        "D" => ExportValue::Text(fields.document_type.to_string()),
+        "E" => ExportValue::Text(fields.document_status.to_string()),
+        "F" => ExportValue::Date(fields.emission_date),
+        "G" => ExportValue::Text(fields.invoice_number.clone()),
+        "H" => ExportValue::Text(fields.atcud.clone()),
+        "L" => fields.non_taxable.into(),
+        "M" => fields.stamp_tax.into(),
+        "N" => ExportValue::Amount(fields.total_tax),
+        "O" => ExportValue::Amount(fields.total_price),
+        "P" => fields.withholding_tax.into(),
+        "Q" => ExportValue::Text(fields.hash.clone()),
+        "R" => ExportValue::Integer(fields.certificate_number),
+        "S" => fields.other_info.clone().map_or(ExportValue::Empty, ExportValue::Text),
+        SUPPLIER_COLUMN => supplier_names.get(&fields.issuer_nif).cloned().map_or(ExportValue::Empty, ExportValue::Text),
+        WARNINGS_COLUMN => {
+            let warnings: Vec<String> = invoice.get_warnings().iter().map(|x| x.to_string()).collect();
+            ExportValue::Text(warnings.join("; "))
+        },
+        _ => {
+            let mut chars = column.chars();
+            match (chars.next(), chars.next().and_then(|x| x.to_digit(10)), chars.next()) {
+                (Some(prefix @ 'I'..='K'), Some(idx), None) => get_vat_column_value(invoice, prefix, idx as u8),
+                _ => ExportValue::Empty,
+            }
+        }
+    }
+}
+
+pub fn load_column_headers(path: &str) -> Result<ColumnHeaderTable, ExportError> {
+    let text = std::fs::read_to_string(path)?;
+    return Ok(serde_json::from_str(&text)?);
+}
+
+// Writes every invoice, sorted by emission date, to the given file
+pub fn export_invoices<'a>(invoices: impl Iterator<Item= &'a Rc<dyn Invoice>>, path: &Path, format: ExportFormat,
+    column_headers: &ColumnHeaderTable, supplier_names: &InvoiceMappingTable) -> Result<(), ExportError> {
+
+    let mut sorted_invoices: Vec<&Rc<dyn Invoice>> = invoices.collect();
+    sorted_invoices.sort_by_key(|x| (x.get_emission_date(), x.get_key()));
+
+    let columns = get_export_columns();
+    let headers: Vec<String> = columns.iter().map(|x| get_column_header(column_headers, x)).collect();
+    let rows: Vec<Vec<ExportValue>> = sorted_invoices
+        .iter()
+        .map(|invoice| columns.iter().map(|column| get_column_value(invoice.as_ref(), column, supplier_names)).collect())
+        .collect();
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    match format {
+        ExportFormat::Csv => csv_export::write_csv(path, &headers, &rows)?,
+        ExportFormat::Xlsx => xlsx_export::write_xlsx(path, &headers, &rows)?,
+    }
+    info!("Exported {} invoices to {}", rows.len(), path.display());
+
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::invoice::invoice_qr::InvoiceQR;
+
+    fn get_values(raw: &str, columns: &[&str]) -> Vec<ExportValue> {
+        let invoice = InvoiceQR::from_raw(raw, NaiveDate::from_ymd_opt(2024, 3, 15).unwrap().and_hms_opt(10, 0, 0).unwrap()).unwrap();
+        return columns.iter().map(|column| get_column_value(&invoice, column, &InvoiceMappingTable::new())).collect();
+    }
+
+    #[test]
+    fn exports_azores_block_without_mainland_vat_under_j() {
+        let raw = "A:123456789*B:999999990*C:PT*D:FT*E:N*F:20240315*G:FT A/1*H:CSDF7T5H-1*I1:0*J1:PT-AC*J7:100.00*J8:16.00*N:16.00*O:116.00*Q:abcd*R:1234";
+        assert_eq!(get_values(raw, &["I1", "I7", "I8", "J1", "J7", "J8", "K1"]), vec![
+            ExportValue::Text("0".to_string()),
+            ExportValue::Empty,
+            ExportValue::Empty,
+            ExportValue::Text("PT-AC".to_string()),
+            ExportValue::Amount(Money::from_cents(10000)),
+            ExportValue::Amount(Money::from_cents(1600)),
+            ExportValue::Empty,
+        ]);
+    }
+
+    #[test]
+    fn exports_madeira_block_under_k_when_j_is_missing() {
+        let raw = "A:123456789*B:999999990*C:PT*D:FT*E:N*F:20240315*G:FT A/2*H:CSDF7T5H-2*I1:PT*I7:100.00*I8:23.00*K1:PT-MA*K7:50.00*K8:11.00*N:34.00*O:184.00*Q:abcd*R:1234";
+        assert_eq!(get_values(raw, &["I1", "I8", "J1", "J8", "K1", "K7", "K8"]), vec![
+            ExportValue::Text("PT".to_string()),
+            ExportValue::Amount(Money::from_cents(2300)),
+            ExportValue::Empty,
+            ExportValue::Empty,
+            ExportValue::Text("PT-MA".to_string()),
+            ExportValue::Amount(Money::from_cents(5000)),
+            ExportValue::Amount(Money::from_cents(1100)),
+        ]);
+    }
+}
@@ -0,0 +1,52 @@
+use std::path::Path;
+
+use chrono::Datelike;
+use rust_xlsxwriter::{ExcelDateTime, Format, Workbook};
+
+use super::{ExportError, ExportValue};
+
+const SHEET_NAME: &str = "Faturas";
+const COLUMN_WIDTH: f64 = 18.0;
+
+pub fn write_xlsx(path: &Path, headers: &[String], rows: &[Vec<ExportValue>]) -> Result<(), ExportError> {
+    let mut workbook = Workbook::new();
+    let header_format = Format::new().set_bold();
+    let amount_format = Format::new().set_num_format("0.00");
+    let date_format = Format::new().set_num_format("yyyy-mm-dd");
+
+    let worksheet = workbook.add_worksheet();
+    worksheet.set_name(SHEET_NAME)?;
+
+    for (col, header) in headers.iter().enumerate() {
+        worksheet.write_string_with_format(0, col as u16, header, &header_format)?;
+        worksheet.set_column_width(col as u16, COLUMN_WIDTH)?;
+    }
+    worksheet.set_freeze_panes(1, 0)?;
+
+    for (row_idx, row) in rows.iter().enumerate() {
+        let row_num = (row_idx + 1) as u32;
+
+        for (col, value) in row.iter().enumerate() {
+            let col = col as u16;
+            match value {
+                ExportValue::Empty => {},
+                ExportValue::Text(text) => {
+                    worksheet.write_string(row_num, col, text)?;
+                },
+                ExportValue::Amount(amount) => {
+                    worksheet.write_number_with_format(row_num, col, amount.to_f64(), &amount_format)?;
+                },
+                ExportValue::Integer(value) => {
+                    worksheet.write_number(row_num, col, *value)?;
+                },
+                ExportValue::Date(date) => {
+                    let excel_date = ExcelDateTime::from_ymd(date.year() as u16, date.month() as u8, date.day() as u8)?;
+                    worksheet.write_date_with_format(row_num, col, &excel_date, &date_format)?;
+                },
+            }
+        }
+    }
+
+    workbook.save(path)?;
+    return Ok(());
+}
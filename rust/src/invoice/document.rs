@@ -0,0 +1,102 @@
+use std::fmt;
+use std::str::FromStr;
+
+// Document type (field D), types without special handling are kept as they were read
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum DocumentType {
+    Invoice,
+    SimplifiedInvoice,
+    InvoiceReceipt,
+    DebitNote,
+    CreditNote,
+    Receipt,
+    Other(String),
+}
+
+impl DocumentType {
+    pub fn get_code(&self) -> &str {
+        match self {
+            DocumentType::Invoice => "FT",
+            DocumentType::SimplifiedInvoice => "FS",
+            DocumentType::InvoiceReceipt => "FR",
+            DocumentType::DebitNote => "ND",
+            DocumentType::CreditNote => "NC",
+            DocumentType::Receipt => "RG",
+            DocumentType::Other(code) => code.as_str(),
+        }
+    }
+
+    // Credit notes reduce the amount owed to the issuer
+    pub fn is_credit(&self) -> bool {
+        return *self == DocumentType::CreditNote;
+    }
+}
+
+impl FromStr for DocumentType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != 2 || !s.bytes().all(|c| c.is_ascii_uppercase()) {
+            return Err(());
+        }
+
+        match s {
+            "FT" => Ok(DocumentType::Invoice),
+            "FS" => Ok(DocumentType::SimplifiedInvoice),
+            "FR" => Ok(DocumentType::InvoiceReceipt),
+            "ND" => Ok(DocumentType::DebitNote),
+            "NC" => Ok(DocumentType::CreditNote),
+            "RG" => Ok(DocumentType::Receipt),
+            _ => Ok(DocumentType::Other(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for DocumentType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.get_code())
+    }
+}
+
+// Document status (field E)
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum DocumentStatus {
+    Normal,
+    SelfBilled,
+    Annulled,
+    Summary,
+    Invoiced,
+}
+
+impl DocumentStatus {
+    pub fn get_code(&self) -> &str {
+        match self {
+            DocumentStatus::Normal => "N",
+            DocumentStatus::SelfBilled => "S",
+            DocumentStatus::Annulled => "A",
+            DocumentStatus::Summary => "R",
+            DocumentStatus::Invoiced => "F",
+        }
+    }
+}
+
+impl FromStr for DocumentStatus {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "N" => Ok(DocumentStatus::Normal),
+            "S" => Ok(DocumentStatus::SelfBilled),
+            "A" => Ok(DocumentStatus::Annulled),
+            "R" => Ok(DocumentStatus::Summary),
+            "F" => Ok(DocumentStatus::Invoiced),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for DocumentStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.get_code())
+    }
+}
@@ -1,47 +1,353 @@
 use std::{borrow::Borrow};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::Write;
 
-use anyhow::Result;
-use super::{InvoiceMappingTable, INVOICE_MAPPING_JSON_PATH, Invoice};
+use anyhow::{anyhow, Result};
+use super::{InvoiceMappingTable, INVOICE_MAPPING_JSON_PATH, Invoice, InvoiceKey};
 use serde_json;
-use super::super::qr_code::QRCode;
-use super::invoice_qr::InvoiceQR;
+use super::super::qr_code::{QRCode, QRCodeOrigin};
+use super::invoice_qr::{InvoiceQR, InvoiceParsingError, QR_FIELD_CODES};
+use super::invoice_store::{InvoiceStore, StoreEntry, StoredScan, INVOICE_STORE_PATH, DEFAULT_SESSION};
+use super::reconciliation_ledger::{Allocation, Assignment, ReconciliationLedger, Unassignment};
+use super::money::Money;
+use super::match_query::MatchQuery;
 use std::sync::mpsc;
-use log::{error, debug};
-use crate::invoice::subset_problem::SubsetSolver;
+use log::{error, debug, info, warn};
+use crate::invoice::subset_problem::{self as subset_problem, SubsetSolver, SubsetSolverType};
+use crate::invoice::subset_problem::search_control::{SearchControl, SearchStopReason};
+use crate::invoice::subset_problem::search_worker::{SubsetSearchWorker, SearchUpdate};
 use crate::invoice::subset_problem::greedy_search::GreedySearchSolver;
+use crate::invoice::subset_problem::exact_search::ExactSearchSolver;
 use std::rc::Rc;
+use std::path::Path;
+use std::time::Duration;
+use crate::export::{self, ColumnHeaderTable, ExportFormat, ExportError, COLUMN_HEADERS_JSON_PATH};
+use chrono::{Local, NaiveDateTime};
+use crate::bank::{self, BankImportError, BankMovement, StatementFormat};
+use crate::bank::csv_import::{self, CsvStatementConfig, BANK_CSV_CONFIG_JSON_PATH};
+use super::reconciliation::{self, MatchConfidence, ReconciliationRow, ReconciliationSettings, ReconciliationStatus};
+
+// QR code that could not be parsed into an invoice, repeated scans of the same code are counted instead of stored
+pub struct RejectedScan {
+    pub raw_data: String,
+    pub error: InvoiceParsingError,
+    pub last_seen: NaiveDateTime,
+    pub count: u32,
+    // File of the last scan, for sources reading images
+    pub origin: Option<QRCodeOrigin>,
+}
+
+pub const DEFAULT_MAX_CANDIDATES: usize = 10;
+pub const DEFAULT_SEARCH_TIMEOUT: Duration = Duration::from_secs(30);
+// Invoices owing more than a movement proposed as paid in part by it
+const MAX_PARTIAL_CANDIDATES: usize = 3;
+
+// Subset of invoices proposed for a target amount, invoices are ordered by emission date
+pub struct InvoiceMatch {
+    pub invoices: Vec<Rc<dyn Invoice>>,
+    // Open balance of each invoice when the match was proposed
+    pub open_balances: Vec<Money>,
+    // Part of the target each invoice would get if the match is accepted
+    pub allocations: Vec<Money>,
+    pub total: Money,
+    pub distance: Money,
+    pub within_tolerance: bool,
+    // Some invoice would keep an open balance after the match
+    pub partial: bool,
+    // Days between the oldest and the newest invoice of the subset
+    pub date_span_days: i64,
+}
+
+impl InvoiceMatch {
+    pub fn new(mut invoices: Vec<(Rc<dyn Invoice>, Money)>, query: &MatchQuery) -> Self {
+        invoices.sort_by_key(|x| (x.0.get_emission_date(), x.0.get_key()));
+        let open_balances: Vec<Money> = invoices.iter().map(|x| x.1).collect();
+        let invoices: Vec<Rc<dyn Invoice>> = invoices.into_iter().map(|x| x.0).collect();
+
+        let total: Money = open_balances.iter().sum();
+        let within_tolerance = query.is_within_tolerance(total);
+        // Inside the tolerance the difference is a bank fee and the invoices are settled
+        let allocations = if within_tolerance { open_balances.clone() } else { reconciliation::allocate_amount(query.target, &open_balances) };
+        let partial = allocations != open_balances;
+
+        let first_date = invoices.first().map(|x| x.get_emission_date());
+        let last_date = invoices.last().map(|x| x.get_emission_date());
+        let date_span_days = match (first_date, last_date) {
+            (Some(first_date), Some(last_date)) => (last_date - first_date).num_days(),
+            _ => 0,
+        };
+
+        Self {
+            invoices: invoices,
+            open_balances: open_balances,
+            allocations: allocations,
+            total: total,
+            distance: (total - query.target).abs(),
+            within_tolerance: within_tolerance,
+            partial: partial,
+            date_span_days: date_span_days,
+        }
+    }
+
+    pub fn get_allocated_total(&self) -> Money {
+        return self.allocations.iter().sum();
+    }
+
+    // Allocations to record in the ledger, invoices that would get nothing are left out
+    pub fn get_allocations(&self) -> Vec<Allocation> {
+        return self.invoices
+            .iter()
+            .zip(self.allocations.iter())
+            .filter(|(_, amount)| **amount != Money::ZERO)
+            .map(|(invoice, amount)| Allocation{invoice: invoice.get_key(), amount: *amount})
+            .collect();
+    }
+}
+
+// State of a background search reported to the UI, matches are the best found so far until finished
+pub struct InvoiceSearchUpdate {
+    pub matches: Vec<InvoiceMatch>,
+    pub progress: f32,
+    pub finished: bool,
+    pub stop_reason: Option<SearchStopReason>,
+    // The exact solver had too many invoices and used the greedy search
+    pub approximate: bool,
+}
+
+// Invoices, with their open balance, the solver picks from and the query it solves once the must include invoices are counted
+struct InvoiceSearchInput {
+    required: Vec<(Rc<dyn Invoice>, Money)>,
+    pool: Vec<(Rc<dyn Invoice>, Money)>,
+    solver_query: MatchQuery,
+}
+
+struct PendingInvoiceSearch {
+    worker: SubsetSearchWorker,
+    input: InvoiceSearchInput,
+    query: MatchQuery,
+}
+
+impl PendingInvoiceSearch {
+    fn start(solver_type: SubsetSolverType, input: InvoiceSearchInput, query: &MatchQuery, max_candidates: usize, timeout: Duration) -> Self {
+        let numbers: Vec<i64> = input.pool.iter().map(|x| x.1.cents()).collect();
+        let worker = SubsetSearchWorker::start(solver_type, numbers, input.solver_query.clone(), max_candidates, Some(timeout));
+        Self {
+            worker: worker,
+            input: input,
+            query: query.clone(),
+        }
+    }
+
+    // Latest state of the search since the last call, if it changed
+    fn check_updates(&mut self) -> Option<InvoiceSearchUpdate> {
+        let update = match self.worker.check_updates() {
+            Some(SearchUpdate::Progress { candidates, progress }) => InvoiceSearchUpdate{
+                matches: InvoiceManager::build_invoice_matches(&self.input, &self.query, &candidates),
+                progress: progress,
+                finished: false,
+                stop_reason: None,
+                approximate: false,
+            },
+            Some(SearchUpdate::Finished { candidates, stop_reason, approximate }) => InvoiceSearchUpdate{
+                matches: InvoiceManager::build_invoice_matches(&self.input, &self.query, &candidates),
+                progress: 1.0,
+                finished: true,
+                stop_reason: stop_reason,
+                approximate: approximate,
+            },
+            // The search thread died without reporting a result
+            None if self.worker.is_finished() => InvoiceSearchUpdate{
+                matches: Vec::new(),
+                progress: 1.0,
+                finished: true,
+                stop_reason: None,
+                approximate: false,
+            },
+            None => return None,
+        };
+        return Some(update);
+    }
+}
 
 pub struct InvoiceManager {
-    invoices: HashMap<String,Rc<dyn Invoice>>,
+    invoices: HashMap<InvoiceKey,Rc<dyn Invoice>>,
+    rejected_scans: Vec<RejectedScan>,
     name_mapping_table: InvoiceMappingTable,
+    name_mapping_error: Option<String>,
+    column_headers: ColumnHeaderTable,
     invoice_recv: mpsc::Receiver<Box<QRCode>>,
 
-    subset_solver: GreedySearchSolver,
+    store: InvoiceStore,
+    stored_scans: Vec<StoredScan>,
+    // Source of each invoice of the session, looked up by the UI for every row it draws
+    invoice_origins: HashMap<InvoiceKey, QRCodeOrigin>,
+    session: String,
+    ledger: ReconciliationLedger,
+
+    greedy_solver: GreedySearchSolver,
+    exact_solver: ExactSearchSolver,
+    solver_type: SubsetSolverType,
+    include_annulled: bool,
+    invoice_search: Option<PendingInvoiceSearch>,
+    search_timeout: Duration,
+
+    bank_csv_config: CsvStatementConfig,
+    reconciliation_rows: Vec<ReconciliationRow>,
+    reconciliation_settings: ReconciliationSettings,
+    // Row being searched
+    reconciliation_search: Option<(usize, PendingInvoiceSearch)>,
 }
 
 
 impl InvoiceManager  {
     
     pub fn new(invoice_recv : mpsc::Receiver<Box<QRCode>>) -> InvoiceManager{
-        let name_mapping_table = InvoiceManager::load_name_mapping_from_file(INVOICE_MAPPING_JSON_PATH);
-        return InvoiceManager{invoices: HashMap::new(), name_mapping_table: name_mapping_table, invoice_recv: invoice_recv,
-            subset_solver: GreedySearchSolver{}};
+
+        let column_headers = export::load_column_headers(COLUMN_HEADERS_JSON_PATH).unwrap_or_else(|error| {
+            error!("Error loading the column headers {}: {}", COLUMN_HEADERS_JSON_PATH, error);
+            ColumnHeaderTable::new()
+        });
+
+        let bank_csv_config = csv_import::load_csv_config(BANK_CSV_CONFIG_JSON_PATH).unwrap_or_else(|error| {
+            error!("Error loading the bank CSV configuration {}: {}", BANK_CSV_CONFIG_JSON_PATH, error);
+            CsvStatementConfig::default()
+        });
+
+        let store = InvoiceStore::new(INVOICE_STORE_PATH);
+        let stored_entries = store.load().unwrap_or_else(|error| {
+            error!("Error loading the invoice store {}: {}", INVOICE_STORE_PATH, error);
+            Vec::new()
+        });
+
+        let mut stored_scans = Vec::new();
+        let mut ledger = ReconciliationLedger::new();
+        for entry in stored_entries {
+            match entry {
+                StoreEntry::Scan(scan) => stored_scans.push(scan),
+                StoreEntry::Assignment(assignment) => ledger.apply_assignment(assignment),
+                StoreEntry::Unassignment(unassignment) => {
+                    if ledger.apply_unassignment(&unassignment).is_none() {
+                        warn!("Stored undo of unknown assignment {}", unassignment.undone_assignment);
+                    }
+                }
+            }
+        }
+        // Resume the session of the last scan
+        let session = stored_scans.last().map(|x| x.session.clone()).unwrap_or(DEFAULT_SESSION.to_string());
+
+        let mut manager = InvoiceManager{invoices: HashMap::new(), rejected_scans: Vec::new(), name_mapping_table: InvoiceMappingTable::new(), name_mapping_error: None, column_headers: column_headers, invoice_recv: invoice_recv,
+            store: store, stored_scans: stored_scans, invoice_origins: HashMap::new(), session: session, ledger: ledger,
+            greedy_solver: GreedySearchSolver{}, exact_solver: ExactSearchSolver{}, solver_type: SubsetSolverType::Exact,
+            include_annulled: false, invoice_search: None, search_timeout: DEFAULT_SEARCH_TIMEOUT,
+            bank_csv_config: bank_csv_config, reconciliation_rows: Vec::new(), reconciliation_search: None,
+            reconciliation_settings: ReconciliationSettings{tolerance: Money::ZERO, date_window: None, max_size: None, max_candidates: DEFAULT_MAX_CANDIDATES}};
+        manager.reload_name_mapping();
+        manager.load_session_invoices();
+        return manager;
+    }
+
+    fn load_session_invoices(&mut self) {
+        self.invoices.clear();
+        self.invoice_origins.clear();
+
+        for scan in self.stored_scans.iter().filter(|x| x.session == self.session) {
+            match InvoiceQR::from_raw(&scan.raw_data, scan.scanned_at) {
+                Ok(invoice) => {
+                    let key = invoice.get_key();
+                    if self.invoices.contains_key(&key) {
+                        continue;
+                    }
+                    if let Some(origin) = scan.get_origin() {
+                        self.invoice_origins.insert(key.clone(), origin);
+                    }
+                    self.invoices.insert(key, Rc::new(invoice));
+                },
+                Err(error) => {
+                    warn!("Skipping stored scan that could not be parsed: {}", error);
+                }
+            }
+        }
+        debug!("Loaded {} invoices from session {}", self.invoices.len(), self.session);
+    }
+
+    // File and page the invoice was read from, when it came from a file source
+    pub fn get_invoice_origin(&self, invoice: &dyn Invoice) -> Option<QRCodeOrigin> {
+        return self.invoice_origins.get(&invoice.get_key()).cloned();
+    }
+
+    pub fn get_session(&self) -> &str {
+        return self.session.as_str();
+    }
+
+    // Sessions found in the store in the order they were created, including the current one
+    pub fn get_sessions(&self) -> Vec<String> {
+        let mut sessions: Vec<String> = Vec::new();
+        for scan in self.stored_scans.iter() {
+            if !sessions.contains(&scan.session) {
+                sessions.push(scan.session.clone());
+            }
+        }
+        if !sessions.contains(&self.session) {
+            sessions.push(self.session.clone());
+        }
+        return sessions;
+    }
+
+    pub fn set_session(&mut self, session: &str) {
+        if self.session == session {
+            return;
+        }
+        self.session = session.to_string();
+        // Results of a running search would point to invoices of the previous session
+        self.invoice_search = None;
+        self.clear_reconciliation();
+        self.load_session_invoices();
     }
 
     pub fn check_qr_channel(&mut self) -> Result<Option<Rc<dyn Invoice>>> {
         for qr in self.invoice_recv.try_iter() {
-            let invoice = Rc::new(InvoiceQR::new(qr)?);
-            let curr_invoice_id = invoice.get_id().to_string();
+            let raw_data = qr.get_data().clone();
+            let origin = qr.get_origin().cloned();
+            let invoice = match InvoiceQR::new(qr) {
+                Ok(invoice) => Rc::new(invoice),
+                Err(error) => {
+                    let now = Local::now().naive_local();
+                    match self.rejected_scans.iter_mut().find(|x| x.raw_data == raw_data) {
+                        Some(rejected) => {
+                            rejected.last_seen = now;
+                            rejected.count += 1;
+                            rejected.origin = origin;
+                        },
+                        None => {
+                            self.rejected_scans.push(RejectedScan{raw_data: raw_data, error: error.clone(), last_seen: now, count: 1, origin: origin});
+                        }
+                    }
+                    return Err(error.into());
+                }
+            };
+            let curr_invoice_key = invoice.get_key();
 
-            if self.invoices.contains_key(&curr_invoice_id) {
+            if self.invoices.contains_key(&curr_invoice_key) {
                 debug!("Invoice already exists");
-                return Ok(Some(self.get_invoice(&curr_invoice_id).unwrap()));
+                return Ok(Some(self.get_invoice(&curr_invoice_key).unwrap()));
             }
 
-            debug!("Found new invoice with id: {}, number of invoices saved: {}", curr_invoice_id, self.invoices.len());
-            self.invoices.insert(curr_invoice_id.clone(),invoice);
-            return Ok(Some(self.get_invoice(&curr_invoice_id).unwrap()));      
+            debug!("Found new invoice with key: {}, number of invoices saved: {}", curr_invoice_key, self.invoices.len());
+            if let Some(origin) = &origin {
+                info!("Invoice {} read from {}", curr_invoice_key, origin);
+            }
+            let scan = StoredScan{session: self.session.clone(), scanned_at: invoice.get_scanned_at(), raw_data: raw_data,
+                source_file: origin.as_ref().map(|x| x.file.clone()), source_page: origin.as_ref().and_then(|x| x.page), source_frame: origin.and_then(|x| x.frame)};
+            if let Err(error) = self.store.append(&StoreEntry::Scan(scan.clone())) {
+                error!("Could not save invoice {} to the store: {}", curr_invoice_key, error);
+            }
+            if let Some(origin) = scan.get_origin() {
+                self.invoice_origins.insert(curr_invoice_key.clone(), origin);
+            }
+            self.stored_scans.push(scan);
+            self.invoices.insert(curr_invoice_key.clone(),invoice);
+            return Ok(Some(self.get_invoice(&curr_invoice_key).unwrap()));      
         }
 
         return Ok(None);
@@ -51,6 +357,65 @@ impl InvoiceManager  {
         return self.name_mapping_table.borrow();
     }
 
+    // Error of the last attempt to load the name mapping file, edits are refused while it is set so the file is not overwritten
+    pub fn get_name_mapping_error(&self) -> Option<&str> {
+        return self.name_mapping_error.as_deref();
+    }
+
+    pub fn reload_name_mapping(&mut self) {
+        match InvoiceManager::load_name_mapping_from_file(INVOICE_MAPPING_JSON_PATH) {
+            Ok(name_mapping_table) => {
+                self.name_mapping_table = name_mapping_table;
+                self.name_mapping_error = None;
+            },
+            Err(error) => {
+                error!("{}", error);
+                self.name_mapping_table = InvoiceMappingTable::new();
+                self.name_mapping_error = Some(error.to_string());
+            }
+        }
+    }
+
+    pub fn get_supplier_name(&self, nif: &str) -> Option<&str> {
+        return self.name_mapping_table.get(nif).map(|x| x.as_str());
+    }
+
+    // Adds a new supplier or renames an existing one
+    pub fn set_supplier_name(&mut self, nif: &str, name: &str) -> Result<()> {
+        if let Some(error) = &self.name_mapping_error {
+            return Err(anyhow!("The name mapping file must be fixed before editing: {}", error));
+        }
+        if nif.is_empty() || name.is_empty() {
+            return Err(anyhow!("Supplier NIF and name cannot be empty"));
+        }
+
+        let mut name_mapping_table = self.name_mapping_table.clone();
+        name_mapping_table.insert(nif.to_string(), name.to_string());
+        InvoiceManager::save_name_mapping_to_file(INVOICE_MAPPING_JSON_PATH, &name_mapping_table)?;
+        self.name_mapping_table = name_mapping_table;
+
+        return Ok(());
+    }
+
+    pub fn remove_supplier(&mut self, nif: &str) -> Result<()> {
+        if let Some(error) = &self.name_mapping_error {
+            return Err(anyhow!("The name mapping file must be fixed before editing: {}", error));
+        }
+
+        let mut name_mapping_table = self.name_mapping_table.clone();
+        if name_mapping_table.remove(nif).is_none() {
+            return Ok(());
+        }
+        InvoiceManager::save_name_mapping_to_file(INVOICE_MAPPING_JSON_PATH, &name_mapping_table)?;
+        self.name_mapping_table = name_mapping_table;
+
+        return Ok(());
+    }
+
+    pub fn get_invoice_count(&self) -> usize {
+        return self.invoices.len();
+    }
+
     pub fn get_invoices(&self) -> impl Iterator<Item= &Rc<dyn Invoice>> {
 
         //Get an iterator of the hashmap as a list of invoices
@@ -58,31 +423,515 @@ impl InvoiceManager  {
         return invoices
     }
 
-    pub fn get_invoice(&self, invoice_id: &str) -> Option<Rc<dyn Invoice>> {
-        return self.invoices.get(invoice_id).map(|x| x.clone());
+    pub fn get_rejected_scans(&self) -> &[RejectedScan] {
+        return self.rejected_scans.as_slice();
+    }
+
+    pub fn clear_rejected_scans(&mut self) {
+        self.rejected_scans.clear();
+    }
+
+    pub fn get_invoice(&self, invoice_key: &InvoiceKey) -> Option<Rc<dyn Invoice>> {
+        return self.invoices.get(invoice_key).map(|x| x.clone());
+    }
+
+    pub fn set_solver_type(&mut self, solver_type: SubsetSolverType) {
+        self.solver_type = solver_type;
+    }
+
+    pub fn get_solver_type(&self) -> SubsetSolverType {
+        return self.solver_type;
+    }
+
+    pub fn set_include_annulled(&mut self, include_annulled: bool) {
+        self.include_annulled = include_annulled;
+    }
+
+    pub fn get_include_annulled(&self) -> bool {
+        return self.include_annulled;
+    }
+
+    // Invoices that count towards totals and subset matching, annulled documents are skipped unless included
+    pub fn get_countable_invoices(&self) -> impl Iterator<Item= &Rc<dyn Invoice>> {
+        let include_annulled = self.include_annulled;
+        return self.get_invoices().filter(move |x| include_annulled || !x.is_annulled());
+    }
+
+    pub fn get_total(&self) -> Money {
+        return self.get_countable_invoices().map(|x| x.get_signed_price()).sum();
+    }
+
+    pub fn export_invoices(&self, path: &Path, format: ExportFormat) -> Result<(), ExportError> {
+        return export::export_invoices(self.get_invoices(), path, format, &self.column_headers, &self.name_mapping_table);
+    }
+
+    fn prepare_invoice_search(&self, query: &MatchQuery) -> Option<InvoiceSearchInput> {
+        let required: Vec<(Rc<dyn Invoice>, Money)> = query.must_include.iter()
+            .filter_map(|x| self.get_invoice(x))
+            .map(|x| { let open_balance = self.get_open_balance(x.as_ref()); (x, open_balance) })
+            .filter(|x| x.1 != Money::ZERO)
+            .collect();
+        if required.len() != query.must_include.len() {
+            warn!("{} invoices that must be included are already settled or not in the current session", query.must_include.len() - required.len());
+        }
+        if query.max_size.map_or(false, |x| required.len() > x) {
+            warn!("{} invoices must be included but at most {} are allowed", required.len(), query.max_size.unwrap_or_default());
+            return None;
+        }
+
+        // Partially paid invoices take part with what is still owed
+        let pool: Vec<(Rc<dyn Invoice>, Money)> = self.get_countable_invoices()
+            .filter(|x| query.accepts(x.as_ref()) && !query.must_include.contains(&x.get_key()))
+            .map(|x| (x.clone(), self.get_open_balance(x.as_ref())))
+            .filter(|x| x.1 != Money::ZERO)
+            .collect();
+
+        // The solver only sees what is left of the target once the required invoices are counted
+        let required_total: Money = required.iter().map(|x| x.1).sum();
+        let mut solver_query = query.clone();
+        solver_query.target = query.target - required_total;
+        solver_query.max_size = query.max_size.map(|x| x - required.len());
+        solver_query.must_include.clear();
+
+        return Some(InvoiceSearchInput{required: required, pool: pool, solver_query: solver_query});
+    }
+
+    // Candidate subsets ranked by distance beyond the tolerance, then by number of invoices and then by how close their dates are
+    // When no subset fits, single invoices owing more than the target are proposed as partial payments
+    fn build_invoice_matches(input: &InvoiceSearchInput, query: &MatchQuery, subsets: &[Vec<usize>]) -> Vec<InvoiceMatch> {
+        let mut matches: Vec<InvoiceMatch> = subset_problem::map_subsets_to_elements(&input.pool, subsets)
+            .into_iter()
+            .map(|candidate| {
+                let mut subset = input.required.clone();
+                subset.extend(candidate.into_iter().cloned());
+                InvoiceMatch::new(subset, query)
+            })
+            .collect();
+
+        if !matches.iter().any(|x| x.within_tolerance) && query.target > Money::ZERO && query.max_size != Some(0) {
+            let mut installments: Vec<&(Rc<dyn Invoice>, Money)> = input.pool.iter().filter(|x| x.1 > input.solver_query.target).collect();
+            installments.sort_by_key(|x| x.1);
+            for installment in installments.into_iter().take(MAX_PARTIAL_CANDIDATES) {
+                let mut subset = input.required.clone();
+                subset.push(installment.clone());
+                matches.push(InvoiceMatch::new(subset, query));
+            }
+        }
+
+        // A partial payment pays exactly the target, it goes after the full matches and before the distant ones
+        matches.sort_by_key(|x| {
+            let excess_distance = if x.partial { Money::ZERO } else { query.get_excess_distance(x.total) };
+            (excess_distance, x.partial, x.invoices.len(), x.date_span_days)
+        });
+        return matches;
+    }
+
+    pub fn get_best_invoice_matches(&self, query: &MatchQuery, max_candidates: usize) -> Vec<InvoiceMatch> {
+        let input = match self.prepare_invoice_search(query) {
+            Some(input) => input,
+            None => return Vec::new(),
+        };
+
+        let numbers: Vec<i64> = input.pool.iter().map(|x| x.1.cents()).collect();
+        let control = SearchControl::unbounded();
+        let subsets = match self.solver_type {
+            SubsetSolverType::Greedy => self.greedy_solver.solve_query_candidates(&numbers, &input.solver_query, max_candidates, &control, &mut |_, _| {}),
+            SubsetSolverType::Exact => self.exact_solver.solve_query_candidates(&numbers, &input.solver_query, max_candidates, &control, &mut |_, _| {}),
+        };
+        return InvoiceManager::build_invoice_matches(&input, query, &subsets);
+    }
+
+    pub fn set_search_timeout(&mut self, search_timeout: Duration) {
+        self.search_timeout = search_timeout;
+    }
+
+    pub fn get_search_timeout(&self) -> Duration {
+        return self.search_timeout;
+    }
+
+    // Starts the search on a background thread, replacing any search still running
+    // Returns false when the query can not be satisfied at all
+    pub fn start_invoice_search(&mut self, query: &MatchQuery, max_candidates: usize) -> bool {
+        self.invoice_search = None;
+        let input = match self.prepare_invoice_search(query) {
+            Some(input) => input,
+            None => return false,
+        };
+
+        self.invoice_search = Some(PendingInvoiceSearch::start(self.solver_type, input, query, max_candidates, self.search_timeout));
+        return true;
+    }
+
+    pub fn cancel_invoice_search(&mut self) {
+        if let Some(invoice_search) = &self.invoice_search {
+            invoice_search.worker.cancel();
+        }
+    }
+
+    pub fn is_searching(&self) -> bool {
+        return self.invoice_search.as_ref().map_or(false, |x| !x.worker.is_finished());
+    }
+
+    // Progress of the running search since the last call, the search is forgotten once it reports it has finished
+    pub fn check_invoice_search(&mut self) -> Option<InvoiceSearchUpdate> {
+        let update = self.invoice_search.as_mut()?.check_updates()?;
+        if update.finished {
+            self.invoice_search = None;
+        }
+        return Some(update);
+    }
+
+    // What is still owed on the invoice after every payment assigned to it
+    pub fn get_open_balance(&self, invoice: &dyn Invoice) -> Money {
+        let price = invoice.get_signed_price();
+        return price - self.ledger.get_invoice_allocated(&self.session, &invoice.get_key(), price);
     }
 
-    pub fn get_best_invoice_match(&self, sum: f64) -> Vec<Rc<dyn Invoice>> {
-        let invoices : Vec<&Rc<dyn Invoice>> = self.invoices.iter().map(|(_,v)| v.borrow()).collect();
-        let solved = self.subset_solver.solve_vector::<Rc<dyn Invoice>>(invoices.as_slice(), (sum*100.0) as i64, |x| (x.get_price() * 100.0) as i64);
-        return solved.iter().map(|x| x.clone().to_owned()).collect();
+    // Part of a debit movement not given to any invoice yet
+    pub fn get_movement_open_amount(&self, movement: &BankMovement) -> Money {
+        return -movement.amount - self.ledger.get_movement_allocated(&self.session, movement);
     }
 
-    fn load_name_mapping_from_file(path: &str) -> InvoiceMappingTable{
-        // Load the first file into a string.
-        let text = std::fs::read_to_string(path);
+    pub fn get_invoice_assignments<'a>(&'a self, invoice_key: &'a InvoiceKey) -> impl Iterator<Item= &'a Assignment> {
+        return self.ledger.get_invoice_assignments(&self.session, invoice_key);
+    }
+
+    pub fn get_assignments(&self) -> impl Iterator<Item= &Assignment> {
+        return self.ledger.get_assignments(&self.session);
+    }
+
+    // Payment typed by hand, the reference tells apart two of the same amount on the same day
+    pub fn create_manual_movement(&self, amount: Money) -> BankMovement {
+        return BankMovement {
+            booking_date: Local::now().date_naive(),
+            amount: -amount,
+            description: "Manual".to_string(),
+            reference: format!("MANUAL-{}", self.ledger.get_next_id()),
+        };
+    }
+
+    // Records the part of the movement given to each invoice, settled invoices are left out of every search from now on
+    pub fn assign_invoices(&mut self, movement: &BankMovement, allocations: &[Allocation]) -> Result<u64> {
+        if allocations.is_empty() {
+            return Err(anyhow!("No invoices to assign"));
+        }
+        for allocation in allocations {
+            let invoice = self.invoices.get(&allocation.invoice).ok_or(anyhow!("Invoice {} is not in the current session", allocation.invoice))?;
+            let open_balance = self.get_open_balance(invoice.as_ref());
+            if allocation.amount.is_negative() != open_balance.is_negative() || allocation.amount.abs() > open_balance.abs() {
+                return Err(anyhow!("Invoice {} has {} open, can not assign {}", allocation.invoice, open_balance, allocation.amount));
+            }
+        }
+
+        let assignment = Assignment{
+            id: self.ledger.get_next_id(),
+            session: self.session.clone(),
+            assigned_at: Local::now().naive_local(),
+            movement: movement.clone(),
+            invoices: allocations.iter().map(|x| x.invoice.clone()).collect(),
+            allocations: allocations.to_vec(),
+        };
+        self.store.append(&StoreEntry::Assignment(assignment.clone()))?;
+        info!("Assigned {} of movement {} to {} invoices", assignment.get_allocated_total(), movement.description, allocations.len());
+
+        let id = assignment.id;
+        let invoices = assignment.invoices.clone();
+        self.ledger.apply_assignment(assignment);
+        self.requeue_conflicting_rows(&invoices);
+        return Ok(id);
+    }
+
+    pub fn undo_assignment(&mut self, id: u64) -> Result<()> {
+        let invoices = match self.ledger.get_assignment(&self.session, id) {
+            Some(assignment) => assignment.invoices.clone(),
+            None => return Err(anyhow!("Assignment {} does not exist", id)),
+        };
+
+        let unassignment = Unassignment{session: self.session.clone(), undone_at: Local::now().naive_local(), undone_assignment: id};
+        self.store.append(&StoreEntry::Unassignment(unassignment.clone()))?;
+        self.ledger.apply_unassignment(&unassignment);
+        info!("Undone assignment {}", id);
 
-        match text {
-            Ok(text) => {
-                // Parse the string into a dynamically-typed JSON structure.
-                let json = serde_json::from_str(&text).unwrap();
-                return json;
+        // The movement of the assignment has money open again and has to be searched again
+        for row in self.reconciliation_rows.iter_mut().filter(|x| x.assignment_ids.contains(&id)) {
+            row.assignment_ids.retain(|x| *x != id);
+            row.matches.clear();
+            row.selected_match = 0;
+            row.status = ReconciliationStatus::Pending;
+        }
+        self.requeue_conflicting_rows(&invoices);
+        return Ok(());
+    }
+
+    // Proposals that use invoices whose open balance just changed are searched again
+    fn requeue_conflicting_rows(&mut self, invoices: &[InvoiceKey]) {
+        for row in self.reconciliation_rows.iter_mut() {
+            let conflicting = row.matches.iter().any(|x| x.invoices.iter().any(|invoice| invoices.contains(&invoice.get_key())));
+            if conflicting && matches!(row.status, ReconciliationStatus::Proposed | ReconciliationStatus::Unmatched) {
+                row.matches.clear();
+                row.selected_match = 0;
+                row.status = ReconciliationStatus::Pending;
+            }
+        }
+    }
+
+    pub fn import_bank_statement(&self, path: &Path, format: StatementFormat) -> Result<Vec<BankMovement>, BankImportError> {
+        return bank::import_statement(path, format, &self.bank_csv_config);
+    }
+
+    // Queues every debit movement for matching, credits can not be paid by the scanned invoices
+    pub fn start_reconciliation(&mut self, movements: Vec<BankMovement>, settings: ReconciliationSettings) {
+        self.reconciliation_search = None;
+        let movements_count = movements.len();
+        self.reconciliation_rows = movements.into_iter().filter(|x| x.is_debit()).map(ReconciliationRow::new).collect();
+        self.reconciliation_settings = settings;
+        info!("Reconciling {} debit movements, {} credit movements skipped", self.reconciliation_rows.len(), movements_count - self.reconciliation_rows.len());
+
+        // Movements of a statement imported before keep what they were assigned and only search for what is left
+        for row_idx in 0..self.reconciliation_rows.len() {
+            let movement = self.reconciliation_rows[row_idx].movement.clone();
+            let assignments: Vec<&Assignment> = self.ledger.get_movement_assignments(&self.session, &movement).collect();
+            if assignments.is_empty() {
+                continue;
+            }
+
+            let assignment_ids: Vec<u64> = assignments.iter().map(|x| x.id).collect();
+            let mut invoices: Vec<(Rc<dyn Invoice>, Money)> = Vec::new();
+            for assignment in assignments {
+                for invoice_key in assignment.invoices.iter() {
+                    if let Some(invoice) = self.invoices.get(invoice_key) {
+                        let allocation = assignment.get_allocation(invoice_key, invoice.get_signed_price()).unwrap_or_default();
+                        invoices.push((invoice.clone(), allocation));
+                    }
+                }
+            }
+            let open_amount = self.get_movement_open_amount(&movement);
+            let query = self.reconciliation_settings.build_query(&movement, -movement.amount);
+
+            let row = &mut self.reconciliation_rows[row_idx];
+            row.assignment_ids = assignment_ids;
+            row.open_amount = open_amount;
+            if self.reconciliation_settings.tolerance >= open_amount.abs() {
+                row.matches = vec![InvoiceMatch::new(invoices, &query)];
+                row.confidence = MatchConfidence::from_matches(&row.matches);
+                row.status = ReconciliationStatus::Accepted;
+            }
+        }
+    }
+
+    // Advances the reconciliation, movements are searched one at a time on the background thread
+    pub fn check_reconciliation(&mut self) {
+        if let Some((row_idx, search)) = self.reconciliation_search.as_mut() {
+            let update = match search.check_updates() {
+                Some(update) => update,
+                None => return,
+            };
+
+            let row = &mut self.reconciliation_rows[*row_idx];
+            row.selected_match = row.selected_match.min(update.matches.len().saturating_sub(1));
+            row.matches = update.matches;
+            if !update.finished {
+                return;
+            }
+
+            row.confidence = MatchConfidence::from_matches(&row.matches);
+            row.status = if row.matches.is_empty() { ReconciliationStatus::Unmatched } else { ReconciliationStatus::Proposed };
+            debug!("Movement {} reconciled with {} confidence", row.movement.description, row.confidence);
+            self.reconciliation_search = None;
+        }
+
+        let row_idx = match self.reconciliation_rows.iter().position(|x| x.status == ReconciliationStatus::Pending) {
+            Some(row_idx) => row_idx,
+            None => return,
+        };
+        let open_amount = self.get_movement_open_amount(&self.reconciliation_rows[row_idx].movement);
+        self.reconciliation_rows[row_idx].open_amount = open_amount;
+        let query = self.reconciliation_settings.build_query(&self.reconciliation_rows[row_idx].movement, open_amount);
+        match self.prepare_invoice_search(&query) {
+            Some(input) => {
+                let search = PendingInvoiceSearch::start(self.solver_type, input, &query, self.reconciliation_settings.max_candidates, self.search_timeout);
+                self.reconciliation_search = Some((row_idx, search));
+                self.reconciliation_rows[row_idx].status = ReconciliationStatus::Searching;
             },
-            Err(_) => {
-                error!("Error loading the name mapping file: {}", path);
-                return InvoiceMappingTable::new();
+            None => self.reconciliation_rows[row_idx].status = ReconciliationStatus::Unmatched,
+        }
+    }
+
+    // Stops the movement being searched, keeping what was found, and skips the ones not searched yet
+    pub fn cancel_reconciliation(&mut self) {
+        if let Some((_, search)) = &self.reconciliation_search {
+            search.worker.cancel();
+        }
+        for row in self.reconciliation_rows.iter_mut().filter(|x| x.status == ReconciliationStatus::Pending) {
+            row.status = ReconciliationStatus::Skipped;
+        }
+    }
+
+    pub fn is_reconciling(&self) -> bool {
+        return self.reconciliation_search.is_some() || self.reconciliation_rows.iter().any(|x| x.status == ReconciliationStatus::Pending);
+    }
+
+    pub fn get_reconciliation_rows(&self) -> &[ReconciliationRow] {
+        return &self.reconciliation_rows;
+    }
+
+    pub fn clear_reconciliation(&mut self) {
+        self.reconciliation_search = None;
+        self.reconciliation_rows.clear();
+    }
+
+    pub fn select_reconciliation_match(&mut self, row_idx: usize, match_idx: usize) {
+        if let Some(row) = self.reconciliation_rows.get_mut(row_idx) {
+            if match_idx < row.matches.len() {
+                row.selected_match = match_idx;
             }
         }
+    }
+
+    // Assigns the selected proposal, a movement that still has money open afterwards is searched again for the rest
+    pub fn accept_reconciliation_row(&mut self, row_idx: usize) -> Result<()> {
+        let row = self.reconciliation_rows.get(row_idx).ok_or(anyhow!("Reconciliation row {} does not exist", row_idx))?;
+        let invoice_match = row.get_selected_match().ok_or(anyhow!("Movement {} has no proposed invoices", row.movement.description))?;
+        let allocations = invoice_match.get_allocations();
+        let movement = row.movement.clone();
+        let previous_status = row.status;
+
+        // Marked first so the row is not requeued as conflicting with its own assignment
+        self.reconciliation_rows[row_idx].status = ReconciliationStatus::Accepted;
+        let id = match self.assign_invoices(&movement, &allocations) {
+            Ok(id) => id,
+            Err(error) => {
+                self.reconciliation_rows[row_idx].status = previous_status;
+                return Err(error);
+            }
+        };
+        let open_amount = self.get_movement_open_amount(&movement);
+        let tolerance = self.reconciliation_settings.tolerance;
 
+        let row = &mut self.reconciliation_rows[row_idx];
+        row.assignment_ids.push(id);
+        row.open_amount = open_amount;
+        // Credit notes taken in full can leave the movement over allocated, that needs another look too
+        if open_amount.abs() > tolerance {
+            row.matches.clear();
+            row.selected_match = 0;
+            row.status = ReconciliationStatus::Pending;
+        }
+        return Ok(());
+    }
+
+    pub fn reject_reconciliation_row(&mut self, row_idx: usize) -> Result<()> {
+        let row = self.reconciliation_rows.get(row_idx).ok_or(anyhow!("Reconciliation row {} does not exist", row_idx))?;
+        for id in row.assignment_ids.clone() {
+            self.undo_assignment(id)?;
+        }
+
+        let open_amount = self.get_movement_open_amount(&self.reconciliation_rows[row_idx].movement);
+        let row = &mut self.reconciliation_rows[row_idx];
+        row.open_amount = open_amount;
+        row.status = ReconciliationStatus::Rejected;
+        return Ok(());
+    }
+
+    pub fn get_best_invoice_match(&self, sum: Money) -> Vec<Rc<dyn Invoice>> {
+        return self.get_best_invoice_matches(&MatchQuery::new(sum), 1).into_iter().next().map(|x| x.invoices).unwrap_or_default();
+    }
+
+    fn load_name_mapping_from_file(path: &str) -> Result<InvoiceMappingTable>{
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                info!("Name mapping file {} not found, starting with an empty table", path);
+                return Ok(InvoiceMappingTable::new());
+            },
+            Err(error) => return Err(anyhow!("Error reading the name mapping file {}: {}", path, error)),
+        };
+
+        let mut json: InvoiceMappingTable = serde_json::from_str(&text).map_err(|error| anyhow!("Invalid name mapping file {}: {}", path, error))?;
+
+        // Older files held the export column labels keyed by QR field letter, those now live in the column headers file
+        let legacy_count = json.len();
+        json.retain(|key, _| !QR_FIELD_CODES.contains(&key.as_str()));
+        if json.len() != legacy_count {
+            warn!("Ignoring {} column labels in name mapping file {}, they belong in {}", legacy_count - json.len(), path, COLUMN_HEADERS_JSON_PATH);
+        }
+        return Ok(json);
     }
-}
\ No newline at end of file
+
+    // Writes to a temporary file first and renames it, so a crash never leaves a half written table
+    fn save_name_mapping_to_file(path: &str, name_mapping_table: &InvoiceMappingTable) -> Result<()>{
+        let sorted_table: BTreeMap<&String, &String> = name_mapping_table.iter().collect();
+        let text = serde_json::to_string_pretty(&sorted_table)?;
+
+        let tmp_path = format!("{}.tmp", path);
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(text.as_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(&tmp_path, path)?;
+
+        return Ok(());
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Column labels shipped in the name mapping file before it held supplier names
+    const LEGACY_NAME_MAPPING: &str = r#"{
+    "A" : "NIF Emitente",
+    "B" : "NIF Adquirente",
+    "C" : "País Adquirente",
+    "D" : "Tipo",
+    "E" : "Estado",
+    "F" : "Data",
+    "G" : "ID fatura",
+    "H" : "ATCUD",
+    "I1" : "(I)Espaço Fiscal",
+    "I2" : "(I)Base isenta",
+    "I3" : "(I)Base reduzida",
+    "I4" : "(I)Total reduzida",
+    "I5" : "(I)Base intermédia",
+    "I6" : "(I)Total intermédia",
+    "I7" : "(I)Base normal",
+    "I8" : "(I)Total normal",
+    "J1" : "(J)Espaço Fiscal",
+    "J2" : "(J)Base isenta",
+    "J3" : "(J)Base reduzida",
+    "J4" : "(J)Total reduzida",
+    "J5" : "(J)Base intermédia",
+    "J6" : "(J)Total intermédia",
+    "J7" : "(J)Base normal",
+    "J8" : "(J)Total normal",
+    "K1" : "(K)Espaço Fiscal",
+    "K2" : "(K)Base isenta",
+    "K3" : "(K)Base reduzida",
+    "K4" : "(K)Total reduzida",
+    "K5" : "(K)Base intermédia",
+    "K6" : "(K)Total intermédia",
+    "K7" : "(K)Base normal",
+    "K8" : "(K)Total normal",
+    "L" : "Não tributavel",
+    "M" : "Selo",
+    "N" : "Total impostos",
+    "O" : "Total com impostos",
+    "P" : "Retenções na fonte",
+    "Q" : "Hash",
+    "R" : "Certifacado",
+    "S" : "Informação adicional"
+}"#;
+
+    #[test]
+    fn skips_column_labels_of_legacy_name_mapping() {
+        let legacy = LEGACY_NAME_MAPPING.replace("\n}", ",\n    \"123456789\" : \"Fornecedor Lda\"\n}");
+        let path = std::env::temp_dir().join(format!("legacy_name_mapping_{}.json", std::process::id()));
+        std::fs::write(&path, legacy).unwrap();
+
+        let table = InvoiceManager::load_name_mapping_from_file(&path.to_string_lossy()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.get("123456789").map(|x| x.as_str()), Some("Fornecedor Lda"));
+    }
+}
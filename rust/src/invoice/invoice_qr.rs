@@ -1,72 +1,335 @@
 use std::collections::HashMap;
-use chrono::{NaiveDate};
+use chrono::{Local, NaiveDate, NaiveDateTime};
 
 use std::str::FromStr;
 use super::super::qr_code::QRCode;
 use super::Invoice;
+use super::document::{DocumentStatus, DocumentType};
+use super::money::Money;
+use super::nif::{self, NifCheck};
+use super::validation::{self, InvoiceWarning};
+use super::vat::{FiscalSpace, VatBreakdown, VatLine};
 
-#[derive(thiserror::Error, Debug)]
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
 pub enum InvoiceParsingError {
-    #[error("The QR code does not have the field {0}")]
-    ErrorMissingKeyFromQRHeaderTable(String),
-    #[error("{0}")]
-    ErrorParsingQRCode(String),
+    #[error("The QR code is empty")]
+    EmptyQRCode,
+    #[error("Entry '{raw}' is not in the <field>:<value> format")]
+    MalformedEntry { raw: String },
+    #[error("Unknown field {field} with value '{value}'")]
+    UnknownField { field: String, value: String },
+    #[error("Field {field} appears more than once, second value is '{value}'")]
+    DuplicateField { field: String, value: String },
+    #[error("The QR code does not have the mandatory field {field}")]
+    MissingField { field: String },
+    #[error("Value '{value}' of field {field} is longer than {max_length} characters")]
+    ValueTooLong { field: String, value: String, max_length: usize },
+    #[error("Value '{value}' of field {field} is not a valid number")]
+    MalformedNumber { field: String, value: String },
+    #[error("Value '{value}' of field {field} is not a valid YYYYMMDD date")]
+    InvalidDate { field: String, value: String },
+    #[error("Value '{value}' is not allowed for field {field}")]
+    InvalidValue { field: String, value: String },
+}
+
+impl InvoiceParsingError {
+    pub fn get_field(&self) -> Option<&str> {
+        match self {
+            InvoiceParsingError::EmptyQRCode | InvoiceParsingError::MalformedEntry { .. } => None,
+            InvoiceParsingError::UnknownField { field, .. }
+            | InvoiceParsingError::DuplicateField { field, .. }
+            | InvoiceParsingError::MissingField { field }
+            | InvoiceParsingError::ValueTooLong { field, .. }
+            | InvoiceParsingError::MalformedNumber { field, .. }
+            | InvoiceParsingError::InvalidDate { field, .. }
+            | InvoiceParsingError::InvalidValue { field, .. } => Some(field.as_str()),
+        }
+    }
+
+    pub fn get_raw_value(&self) -> Option<&str> {
+        match self {
+            InvoiceParsingError::EmptyQRCode | InvoiceParsingError::MissingField { .. } => None,
+            InvoiceParsingError::MalformedEntry { raw } => Some(raw.as_str()),
+            InvoiceParsingError::UnknownField { value, .. }
+            | InvoiceParsingError::DuplicateField { value, .. }
+            | InvoiceParsingError::ValueTooLong { value, .. }
+            | InvoiceParsingError::MalformedNumber { value, .. }
+            | InvoiceParsingError::InvalidDate { value, .. }
+            | InvoiceParsingError::InvalidValue { value, .. } => Some(value.as_str()),
+        }
+    }
+}
+
+// Every field of the AT specification in the order they appear on the QR code
+pub const QR_FIELD_CODES: [&str; 40] = [
+    "A", "B", "C", "D", "E", "F", "G", "H",
+    "I1", "I2", "I3", "I4", "I5", "I6", "I7", "I8",
+    "J1", "J2", "J3", "J4", "J5", "J6", "J7", "J8",
+    "K1", "K2", "K3", "K4", "K5", "K6", "K7", "K8",
+    "L", "M", "N", "O", "P", "Q", "R", "S",
+];
+
+// Maximum length of each field as defined by the AT specification, None if the field is unknown
+fn get_field_max_length(field: &str) -> Option<usize> {
+    match field {
+        "A" => Some(9),
+        "B" => Some(30),
+        "C" => Some(12),
+        "D" => Some(2),
+        "E" => Some(1),
+        "F" => Some(8),
+        "G" => Some(60),
+        "H" => Some(70),
+        "I1" | "J1" | "K1" => Some(5),
+        "I2" | "I3" | "I4" | "I5" | "I6" | "I7" | "I8" => Some(16),
+        "J2" | "J3" | "J4" | "J5" | "J6" | "J7" | "J8" => Some(16),
+        "K2" | "K3" | "K4" | "K5" | "K6" | "K7" | "K8" => Some(16),
+        "L" | "M" | "N" | "O" | "P" => Some(16),
+        "Q" => Some(4),
+        "R" => Some(4),
+        "S" => Some(65),
+        _ => None,
+    }
+}
+
+// Splits the raw QR string into its fields, rejecting unknown, repeated and oversized entries
+fn parse_qr_entries(data: &str) -> Result<HashMap<String,String>, InvoiceParsingError> {
+    if data.is_empty() {
+        return Err(InvoiceParsingError::EmptyQRCode);
+    }
+
+    let mut entries = HashMap::new();
+    for entry in data.split('*') {
+        let (key, val) = entry.split_once(':').ok_or(InvoiceParsingError::MalformedEntry{raw: entry.to_string()})?;
+
+        let max_length = get_field_max_length(key).ok_or(InvoiceParsingError::UnknownField{field: key.to_string(), value: val.to_string()})?;
+        if val.chars().count() > max_length {
+            return Err(InvoiceParsingError::ValueTooLong{field: key.to_string(), value: val.to_string(), max_length: max_length});
+        }
+
+        if entries.insert(key.to_string(), val.to_string()).is_some() {
+            return Err(InvoiceParsingError::DuplicateField{field: key.to_string(), value: val.to_string()});
+        }
+    }
+
+    return Ok(entries);
+}
+
+fn get_field_str<'a>(data: &'a HashMap<String,String>, field: &str) -> Result<&'a str, InvoiceParsingError> {
+    return data.get(field).map(|x| x.as_str()).ok_or(InvoiceParsingError::MissingField{field: field.to_string()});
 }
 
 fn get_field_error<T: FromStr>(data: &HashMap<String,String>, field: &str ) -> Result<T, InvoiceParsingError> {
-    let str_value = data.get(field).ok_or(InvoiceParsingError::ErrorParsingQRCode(format!("Could not find {} in QR code", field)))?;
-    
-    str_value.parse::<T>().map_err(|_| InvoiceParsingError::ErrorParsingQRCode(format!("Error parsing '{}' for field {}", str_value, field)))
+    let str_value = get_field_str(data, field)?;
+
+    str_value.parse::<T>().map_err(|_| InvoiceParsingError::InvalidValue{field: field.to_string(), value: str_value.to_string()})
+}
+
+fn get_number_field_error<T: FromStr>(data: &HashMap<String,String>, field: &str ) -> Result<T, InvoiceParsingError> {
+    let str_value = get_field_str(data, field)?;
+
+    str_value.parse::<T>().map_err(|_| InvoiceParsingError::MalformedNumber{field: field.to_string(), value: str_value.to_string()})
+}
+
+fn get_optional_number_field_error<T: FromStr>(data: &HashMap<String,String>, field: &str ) -> Result<Option<T>, InvoiceParsingError> {
+    if !data.contains_key(field) {
+        return Ok(None);
+    }
+    return get_number_field_error::<T>(data, field).map(Some);
+}
+
+fn get_optional_field_error<T: FromStr>(data: &HashMap<String,String>, field: &str ) -> Result<Option<T>, InvoiceParsingError> {
+    if !data.contains_key(field) {
+        return Ok(None);
+    }
+    return get_field_error::<T>(data, field).map(Some);
+}
+
+fn get_date_field_error(data: &HashMap<String,String>, field: &str ) -> Result<NaiveDate, InvoiceParsingError> {
+    let str_value = get_field_str(data, field)?;
+
+    NaiveDate::parse_from_str(str_value,"%Y%m%d").map_err(|_| InvoiceParsingError::InvalidDate{field: field.to_string(), value: str_value.to_string()})
+}
+
+// Prefixes of the VAT blocks, one per fiscal space on the document
+pub const VAT_BLOCK_PREFIXES: [char; 3] = ['I', 'J', 'K'];
+
+// Builds the VAT breakdown of the block with the given prefix (I, J or K)
+fn get_vat_breakdown_error(data: &HashMap<String,String>, prefix: char) -> Result<Option<VatBreakdown>, InvoiceParsingError> {
+    let field = |idx: u8| format!("{}{}", prefix, idx);
+
+    let fiscal_space_str = match data.get(&field(1)) {
+        Some(fiscal_space_str) => fiscal_space_str,
+        None => return Ok(None),
+    };
+
+    // Documents without VAT have the field I1 set to 0
+    if fiscal_space_str == "0" {
+        return Ok(None);
+    }
+
+    let fiscal_space = get_field_error::<FiscalSpace>(data, &field(1))?;
+    let get_line = |base_idx: u8, vat_idx: Option<u8>| -> Result<Option<VatLine>, InvoiceParsingError> {
+        let taxable_base = get_optional_number_field_error::<Money>(data, &field(base_idx))?;
+        let vat = match vat_idx {
+            Some(vat_idx) => get_optional_number_field_error::<Money>(data, &field(vat_idx))?,
+            None => None,
+        };
+
+        if taxable_base.is_none() && vat.is_none() {
+            return Ok(None);
+        }
+        return Ok(Some(VatLine{taxable_base: taxable_base.unwrap_or_default(), vat: vat.unwrap_or_default()}));
+    };
+
+    let mut breakdown = VatBreakdown::new(fiscal_space);
+    breakdown.exempt = get_line(2, None)?;
+    breakdown.reduced = get_line(3, Some(4))?;
+    breakdown.intermediate = get_line(5, Some(6))?;
+    breakdown.normal = get_line(7, Some(8))?;
+
+    return Ok(Some(breakdown));
+}
+
+// Every field of the AT QR code specification (ETCODEQR), keyed by its letter
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvoiceQRFields {
+    pub issuer_nif: String,                     // A
+    pub acquirer_nif: String,                   // B
+    pub acquirer_country: String,               // C
+    pub document_type: DocumentType,            // D
+    pub document_status: DocumentStatus,        // E
+    pub emission_date: NaiveDate,               // F
+    pub invoice_number: String,                 // G
+    pub atcud: String,                          // H
+    pub vat_breakdowns: [Option<VatBreakdown>; 3], // I, J and K blocks by position, None when absent or set to 0
+    pub non_taxable: Option<Money>,             // L
+    pub stamp_tax: Option<Money>,               // M
+    pub total_tax: Money,                       // N
+    pub total_price: Money,                     // O
+    pub withholding_tax: Option<Money>,         // P
+    pub hash: String,                           // Q
+    pub certificate_number: u32,                // R
+    pub other_info: Option<String>,             // S
+}
+
+impl InvoiceQRFields {
+    pub fn get_vat_breakdown(&self, fiscal_space: FiscalSpace) -> Option<&VatBreakdown> {
+        return self.vat_breakdowns.iter().flatten().find(|breakdown| breakdown.fiscal_space == fiscal_space);
+    }
+
+    // Block with the given prefix (I, J or K)
+    pub fn get_vat_block(&self, prefix: char) -> Option<&VatBreakdown> {
+        let idx = VAT_BLOCK_PREFIXES.iter().position(|x| *x == prefix)?;
+        return self.vat_breakdowns[idx].as_ref();
+    }
+
+    // Sum of the VAT lines of every fiscal space, which should match the total tax (N) minus the stamp tax (M)
+    pub fn total_vat(&self) -> Money {
+        return self.vat_breakdowns.iter().flatten().map(|breakdown| breakdown.total_vat()).sum();
+    }
+
+    pub fn total_taxable_base(&self) -> Money {
+        return self.vat_breakdowns.iter().flatten().map(|breakdown| breakdown.total_taxable_base()).sum();
+    }
 }
 
 pub struct InvoiceQR {
-    atcud: String,
-    invoice_number: String,
-    emission_date: NaiveDate,
-    total_price: f64,
+    fields: InvoiceQRFields,
+    issuer_nif_check: NifCheck,
+    acquirer_nif_check: NifCheck,
+    warnings: Vec<InvoiceWarning>,
+    raw_data: String,
+    scanned_at: NaiveDateTime,
 }
 
 impl InvoiceQR{
     pub fn new(qr_code: Box<QRCode>) -> Result<InvoiceQR, InvoiceParsingError>{
-        let data = qr_code.get_data();
-
-        let tmp_map_result: Result<HashMap<String,String>, InvoiceParsingError> = data
-            .split('*')
-            .map(|s| {
-                let idx_split = s.find(":").ok_or(InvoiceParsingError::ErrorMissingKeyFromQRHeaderTable(s.to_string()))?;
-                let (key,val) = s.split_at(idx_split);
-
-                return Ok((key.to_string(), val[1..].to_string()))
-            })
-            .collect();
-        let tmp_map = tmp_map_result?;
-        
-        let emission_date_str = get_field_error::<String>(&tmp_map, "F")?; 
-        let emission_date = NaiveDate::parse_from_str(emission_date_str.as_str(),"%Y%m%d").map_err(|_| InvoiceParsingError::ErrorParsingQRCode(format!("'{}' for field {}", emission_date_str, "F")))?;
-        return Ok(InvoiceQR{            
-            atcud: get_field_error::<String>(&tmp_map, "H")?,
-            invoice_number: get_field_error::<String>(&tmp_map, "G")?,
+        return InvoiceQR::from_raw(qr_code.get_data(), Local::now().naive_local());
+    }
+
+    // Parses the decoded QR string, used as well to rebuild invoices from the store
+    pub fn from_raw(data: &str, scanned_at: NaiveDateTime) -> Result<InvoiceQR, InvoiceParsingError>{
+        let tmp_map = parse_qr_entries(data)?;
+        let emission_date = get_date_field_error(&tmp_map, "F")?;
+
+        // I1 is mandatory even when the document has no VAT
+        get_field_str(&tmp_map, "I1")?;
+        // A block set to 0 keeps its position, J data of an I1:0 document stays in J
+        let mut vat_breakdowns: [Option<VatBreakdown>; 3] = Default::default();
+        for (idx, prefix) in VAT_BLOCK_PREFIXES.into_iter().enumerate() {
+            vat_breakdowns[idx] = get_vat_breakdown_error(&tmp_map, prefix)?;
+        }
+
+        let fields = InvoiceQRFields{
+            issuer_nif: get_field_error::<String>(&tmp_map, "A")?,
+            acquirer_nif: get_field_error::<String>(&tmp_map, "B")?,
+            acquirer_country: get_field_error::<String>(&tmp_map, "C")?,
+            document_type: get_field_error::<DocumentType>(&tmp_map, "D")?,
+            document_status: get_field_error::<DocumentStatus>(&tmp_map, "E")?,
             emission_date: emission_date,
-            total_price: get_field_error::<f64>(&tmp_map, "O")?,
-        });
+            invoice_number: get_field_error::<String>(&tmp_map, "G")?,
+            atcud: get_field_error::<String>(&tmp_map, "H")?,
+            vat_breakdowns: vat_breakdowns,
+            non_taxable: get_optional_number_field_error::<Money>(&tmp_map, "L")?,
+            stamp_tax: get_optional_number_field_error::<Money>(&tmp_map, "M")?,
+            total_tax: get_number_field_error::<Money>(&tmp_map, "N")?,
+            total_price: get_number_field_error::<Money>(&tmp_map, "O")?,
+            withholding_tax: get_optional_number_field_error::<Money>(&tmp_map, "P")?,
+            hash: get_field_error::<String>(&tmp_map, "Q")?,
+            certificate_number: get_number_field_error::<u32>(&tmp_map, "R")?,
+            other_info: get_optional_field_error::<String>(&tmp_map, "S")?,
+        };
+
+        let issuer_nif_check = nif::check_nif(&fields.issuer_nif);
+        let acquirer_nif_check = nif::check_acquirer_nif(&fields.acquirer_nif, &fields.acquirer_country);
+
+        let warnings = validation::validate_invoice(&fields, issuer_nif_check, acquirer_nif_check, Local::now().date_naive());
+
+        return Ok(InvoiceQR{fields: fields, issuer_nif_check: issuer_nif_check, acquirer_nif_check: acquirer_nif_check, warnings: warnings,
+            raw_data: data.to_string(), scanned_at: scanned_at});
     }
 
 }
 
 impl Invoice for InvoiceQR {
     fn get_id(&self) -> &str {
-        return self.invoice_number.as_str();
+        return self.fields.invoice_number.as_str();
     }
 
-    fn get_price(&self) -> f64 {
-        return self.total_price;
+    fn get_price(&self) -> Money {
+        return self.fields.total_price;
     }
 
     fn get_emission_date(&self) -> NaiveDate {
-        return self.emission_date;
+        return self.fields.emission_date;
     }
 
     fn get_atcud(&self) -> &str {
-        return self.atcud.as_str();
+        return self.fields.atcud.as_str();
+    }
+
+    fn get_fields(&self) -> &InvoiceQRFields {
+        return &self.fields;
+    }
+
+    fn get_issuer_nif_check(&self) -> NifCheck {
+        return self.issuer_nif_check;
     }
-}
\ No newline at end of file
+
+    fn get_acquirer_nif_check(&self) -> NifCheck {
+        return self.acquirer_nif_check;
+    }
+
+    fn get_warnings(&self) -> &[InvoiceWarning] {
+        return self.warnings.as_slice();
+    }
+
+    fn get_raw_data(&self) -> &str {
+        return self.raw_data.as_str();
+    }
+
+    fn get_scanned_at(&self) -> NaiveDateTime {
+        return self.scanned_at;
+    }
+}
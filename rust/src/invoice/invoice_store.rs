@@ -0,0 +1,103 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::NaiveDateTime;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use super::reconciliation_ledger::{Assignment, Unassignment};
+use crate::qr_code::QRCodeOrigin;
+
+pub const INVOICE_STORE_PATH: &str = "invoices.jsonl";
+pub const DEFAULT_SESSION: &str = "default";
+
+#[derive(thiserror::Error, Debug)]
+pub enum InvoiceStoreError {
+    #[error("Could not access the invoice store: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not serialize the invoice store entry: {0}")]
+    Serialization(#[from] serde_json::Error),
+}
+
+// Single accepted scan, the invoice is rebuilt from the raw QR string when loading
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StoredScan {
+    pub session: String,
+    pub scanned_at: NaiveDateTime,
+    pub raw_data: String,
+    // Image, PDF or video the QR code was read from, None for live sources
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_file: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_page: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_frame: Option<u64>,
+}
+
+impl StoredScan {
+    pub fn get_origin(&self) -> Option<QRCodeOrigin> {
+        return self.source_file.as_ref().map(|x| QRCodeOrigin{file: x.clone(), page: self.source_page, frame: self.source_frame});
+    }
+}
+
+// Line of the store, told apart by their fields so journals written before assignments existed still load
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum StoreEntry {
+    Scan(StoredScan),
+    Assignment(Assignment),
+    Unassignment(Unassignment),
+}
+
+// Append only JSON lines journal, one entry per line, so a crash can at most lose the line being written
+pub struct InvoiceStore {
+    path: PathBuf,
+}
+
+impl InvoiceStore {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn get_path(&self) -> &Path {
+        return self.path.as_path();
+    }
+
+    pub fn load(&self) -> Result<Vec<StoreEntry>, InvoiceStoreError> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => return Err(error.into()),
+        };
+
+        let mut entries = Vec::new();
+        for (idx, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match serde_json::from_str::<StoreEntry>(&line) {
+                Ok(entry) => entries.push(entry),
+                Err(error) => warn!("Skipping corrupted line {} of {}: {}", idx + 1, self.path.display(), error),
+            }
+        }
+
+        return Ok(entries);
+    }
+
+    pub fn append(&self, entry: &StoreEntry) -> Result<(), InvoiceStoreError> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        file.write_all(line.as_bytes())?;
+        // A scan the user saw accepted must survive a power cut
+        file.sync_data()?;
+
+        return Ok(());
+    }
+}
@@ -0,0 +1,82 @@
+use chrono::{Duration, NaiveDate};
+
+use super::{Invoice, InvoiceKey};
+use super::money::Money;
+
+// Constraints for matching a set of invoices against an amount, usually a bank movement
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchQuery {
+    pub target: Money,
+    // Difference to the target still accepted as a match, e.g. bank fees
+    pub tolerance: Money,
+    // Only invoices emitted between these dates, both inclusive
+    pub date_from: Option<NaiveDate>,
+    pub date_to: Option<NaiveDate>,
+    pub issuer_nif: Option<String>,
+    pub max_size: Option<usize>,
+    pub must_include: Vec<InvoiceKey>,
+}
+
+impl MatchQuery {
+    pub fn new(target: Money) -> Self {
+        Self {
+            target: target,
+            tolerance: Money::ZERO,
+            date_from: None,
+            date_to: None,
+            issuer_nif: None,
+            max_size: None,
+            must_include: Vec::new(),
+        }
+    }
+
+    pub fn with_tolerance(mut self, tolerance: Money) -> Self {
+        self.tolerance = tolerance.abs();
+        return self;
+    }
+
+    // Window of days_before days before and days_after days after the given date
+    pub fn with_date_window(mut self, date: NaiveDate, days_before: i64, days_after: i64) -> Self {
+        self.date_from = Some(date - Duration::days(days_before));
+        self.date_to = Some(date + Duration::days(days_after));
+        return self;
+    }
+
+    pub fn with_issuer_nif(mut self, issuer_nif: &str) -> Self {
+        self.issuer_nif = Some(issuer_nif.to_string());
+        return self;
+    }
+
+    pub fn with_max_size(mut self, max_size: usize) -> Self {
+        self.max_size = Some(max_size);
+        return self;
+    }
+
+    pub fn with_must_include(mut self, must_include: Vec<InvoiceKey>) -> Self {
+        self.must_include = must_include;
+        return self;
+    }
+
+    // Whether the invoice passes the date and issuer filters, must include invoices are checked by the caller
+    pub fn accepts(&self, invoice: &dyn Invoice) -> bool {
+        let emission_date = invoice.get_emission_date();
+        if self.date_from.map_or(false, |x| emission_date < x) || self.date_to.map_or(false, |x| emission_date > x) {
+            return false;
+        }
+
+        return self.issuer_nif.as_ref().map_or(true, |x| invoice.get_issuer_nif() == x);
+    }
+
+    pub fn is_within_tolerance(&self, total: Money) -> bool {
+        return (total - self.target).abs() <= self.tolerance;
+    }
+
+    // Distance to the target not covered by the tolerance
+    pub fn get_excess_distance(&self, total: Money) -> Money {
+        let distance = (total - self.target).abs();
+        if distance <= self.tolerance {
+            return Money::ZERO;
+        }
+        return distance - self.tolerance;
+    }
+}
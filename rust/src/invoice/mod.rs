@@ -1,25 +1,96 @@
 use std::collections::HashMap;
+use std::fmt;
 use std::hash::{Hash, Hasher};
-use chrono::{NaiveDate};
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
 
+pub mod document;
 pub mod invoice_qr;
 pub mod invoice_manager;
+pub mod invoice_store;
+pub mod match_query;
+pub mod money;
+pub mod nif;
+pub mod reconciliation;
+pub mod reconciliation_ledger;
 pub mod subset_problem;
+pub mod validation;
+pub mod vat;
+
+use document::DocumentStatus;
+use invoice_qr::InvoiceQRFields;
+use money::Money;
+use nif::NifCheck;
+use validation::InvoiceWarning;
 
 pub type InvoiceMappingTable = HashMap<String, String>;
 
 pub const INVOICE_MAPPING_JSON_PATH: &str = "name_mapping.json";
 
+// Identity of an invoice, document numbers are only unique per issuer
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct InvoiceKey {
+    pub issuer_nif: String,
+    pub document_number: String,
+}
+
+impl InvoiceKey {
+    pub fn new(issuer_nif: &str, document_number: &str) -> Self {
+        Self {
+            issuer_nif: issuer_nif.to_string(),
+            document_number: document_number.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for InvoiceKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.document_number, self.issuer_nif)
+    }
+}
+
 pub trait Invoice{
     fn get_id(&self) -> &str;
-    fn get_price(&self) -> f64;
+    fn get_price(&self) -> Money;
     fn get_emission_date(&self) -> NaiveDate;
     fn get_atcud(&self) -> &str;
+    fn get_fields(&self) -> &InvoiceQRFields;
+    fn get_issuer_nif_check(&self) -> NifCheck;
+    fn get_acquirer_nif_check(&self) -> NifCheck;
+    fn get_warnings(&self) -> &[InvoiceWarning];
+    fn get_raw_data(&self) -> &str;
+    fn get_scanned_at(&self) -> NaiveDateTime;
+
+    fn is_annulled(&self) -> bool {
+        return self.get_fields().document_status == DocumentStatus::Annulled;
+    }
+
+    // Amount as it counts towards reconciliation, credit notes are negative
+    fn get_signed_price(&self) -> Money {
+        if self.get_fields().document_type.is_credit() {
+            return -self.get_price();
+        }
+        return self.get_price();
+    }
+
+    fn get_key(&self) -> InvoiceKey {
+        return InvoiceKey::new(self.get_issuer_nif(), self.get_id());
+    }
+
+    fn get_issuer_nif(&self) -> &str {
+        return self.get_fields().issuer_nif.as_str();
+    }
+
+    // True if any of the NIFs failed the check digit or the invoice was issued to the generic consumer
+    fn has_nif_issues(&self) -> bool {
+        return self.get_issuer_nif_check() != NifCheck::Valid
+            || matches!(self.get_acquirer_nif_check(), NifCheck::Invalid | NifCheck::GenericConsumer);
+    }
 }
 
 impl PartialEq for dyn Invoice + '_ {
     fn eq(&self, other: &Self) -> bool {
-        self.get_id() == other.get_id()
+        self.get_key() == other.get_key()
     }
 }
 
@@ -27,6 +98,6 @@ impl Eq for dyn Invoice + '_ {}
 
 impl Hash for dyn Invoice {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        self.get_id().hash(state);
+        self.get_key().hash(state);
     }
 }
\ No newline at end of file
@@ -0,0 +1,202 @@
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Neg, Sub, SubAssign};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+// Fixed point currency amount stored as integer cents, so sums are exact
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Money(i64);
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum MoneyParsingError {
+    #[error("'{0}' is not a valid amount")]
+    InvalidAmount(String),
+    #[error("'{0}' has more than two decimal places")]
+    TooManyDecimals(String),
+    #[error("'{0}' is out of range")]
+    OutOfRange(String),
+}
+
+impl Money {
+    pub const ZERO: Money = Money(0);
+
+    pub const fn from_cents(cents: i64) -> Self {
+        Money(cents)
+    }
+
+    // Rounds to the nearest cent, only meant for values typed in the UI
+    pub fn from_f64(value: f64) -> Self {
+        Money((value * 100.0).round() as i64)
+    }
+
+    pub fn cents(&self) -> i64 {
+        return self.0;
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        return self.0 as f64 / 100.0;
+    }
+
+    pub fn abs(&self) -> Self {
+        Money(self.0.abs())
+    }
+
+    pub fn is_negative(&self) -> bool {
+        return self.0 < 0;
+    }
+}
+
+impl FromStr for Money {
+    type Err = MoneyParsingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || MoneyParsingError::InvalidAmount(s.to_string());
+
+        let (negative, unsigned) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        let (units_str, decimals_str) = match unsigned.split_once('.') {
+            Some((units, decimals)) => (units, decimals),
+            None => (unsigned, ""),
+        };
+
+        if units_str.is_empty() || !units_str.bytes().all(|c| c.is_ascii_digit()) || !decimals_str.bytes().all(|c| c.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        if decimals_str.len() > 2 {
+            return Err(MoneyParsingError::TooManyDecimals(s.to_string()));
+        }
+
+        let out_of_range = || MoneyParsingError::OutOfRange(s.to_string());
+        let units = units_str.parse::<i64>().map_err(|_| out_of_range())?;
+        let decimals = match decimals_str.len() {
+            0 => 0,
+            1 => decimals_str.parse::<i64>().map_err(|_| invalid())? * 10,
+            _ => decimals_str.parse::<i64>().map_err(|_| invalid())?,
+        };
+
+        let cents = units.checked_mul(100).and_then(|x| x.checked_add(decimals)).ok_or_else(out_of_range)?;
+        return Ok(Money(if negative { -cents } else { cents }));
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let abs = self.0.unsigned_abs();
+        write!(f, "{}{}.{:02}", sign, abs / 100, abs % 100)
+    }
+}
+
+impl Add for Money {
+    type Output = Money;
+
+    fn add(self, other: Money) -> Money {
+        Money(self.0 + other.0)
+    }
+}
+
+impl AddAssign for Money {
+    fn add_assign(&mut self, other: Money) {
+        self.0 += other.0;
+    }
+}
+
+impl Sub for Money {
+    type Output = Money;
+
+    fn sub(self, other: Money) -> Money {
+        Money(self.0 - other.0)
+    }
+}
+
+impl SubAssign for Money {
+    fn sub_assign(&mut self, other: Money) {
+        self.0 -= other.0;
+    }
+}
+
+impl Neg for Money {
+    type Output = Money;
+
+    fn neg(self) -> Money {
+        Money(-self.0)
+    }
+}
+
+impl Sum for Money {
+    fn sum<I: Iterator<Item = Money>>(iter: I) -> Money {
+        iter.fold(Money::ZERO, |acc, x| acc + x)
+    }
+}
+
+impl<'a> Sum<&'a Money> for Money {
+    fn sum<I: Iterator<Item = &'a Money>>(iter: I) -> Money {
+        iter.fold(Money::ZERO, |acc, x| acc + *x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Result<Money, MoneyParsingError> {
+        return s.parse::<Money>();
+    }
+
+    // 0.29 is 0.28999... as a float, the reason amounts are kept in cents
+    #[test]
+    fn parses_cents_exactly() {
+        assert_eq!(parse("0.29"), Ok(Money::from_cents(29)));
+        assert_eq!(parse("1234.56"), Ok(Money::from_cents(123456)));
+        assert_eq!(parse("0.01"), Ok(Money::from_cents(1)));
+    }
+
+    #[test]
+    fn parses_negative_amounts() {
+        assert_eq!(parse("-0.29"), Ok(Money::from_cents(-29)));
+        assert_eq!(parse("-12.50"), Ok(Money::from_cents(-1250)));
+        assert!(parse("-0.29").unwrap().is_negative());
+    }
+
+    #[test]
+    fn parses_one_and_zero_decimals() {
+        assert_eq!(parse("12.5"), Ok(Money::from_cents(1250)));
+        assert_eq!(parse("12"), Ok(Money::from_cents(1200)));
+        assert_eq!(parse("12."), Ok(Money::from_cents(1200)));
+        assert_eq!(parse("0"), Ok(Money::ZERO));
+    }
+
+    #[test]
+    fn rejects_more_than_two_decimals() {
+        assert_eq!(parse("0.295"), Err(MoneyParsingError::TooManyDecimals("0.295".to_string())));
+        assert_eq!(parse("-1.000"), Err(MoneyParsingError::TooManyDecimals("-1.000".to_string())));
+    }
+
+    #[test]
+    fn rejects_invalid_amounts() {
+        for amount in ["", "-", ".50", "1,50", "1.5a", "+1.00", "1.-5", "--1"] {
+            assert_eq!(parse(amount), Err(MoneyParsingError::InvalidAmount(amount.to_string())), "{}", amount);
+        }
+        assert_eq!(parse("99999999999999999999"), Err(MoneyParsingError::OutOfRange("99999999999999999999".to_string())));
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for cents in [0, 1, 10, 29, 100, 123456, -1, -29, -1250, i64::MAX, i64::MIN + 1] {
+            let money = Money::from_cents(cents);
+            assert_eq!(parse(&money.to_string()), Ok(money), "{}", money);
+        }
+        assert_eq!(Money::from_cents(-5).to_string(), "-0.05");
+        assert_eq!(Money::from_cents(1200).to_string(), "12.00");
+    }
+
+    #[test]
+    fn sums_are_exact() {
+        let amounts: Vec<Money> = ["0.10", "0.20", "0.29", "-0.09"].iter().map(|x| parse(x).unwrap()).collect();
+        assert_eq!(amounts.iter().sum::<Money>(), Money::from_cents(50));
+    }
+}
@@ -0,0 +1,117 @@
+// NIF used on invoices issued to final consumers who did not ask for their own NIF
+pub const GENERIC_CONSUMER_NIF: &str = "999999990";
+
+pub const NIF_LENGTH: usize = 9;
+
+// First digit of NIFs of individuals (1, 2, 3), companies (5), public bodies (6), sole traders (8) and other entities (9)
+const NIF_FIRST_DIGITS: [u8; 7] = [b'1', b'2', b'3', b'5', b'6', b'8', b'9'];
+// Numbers starting with 4 or 7 are only assigned within these ranges, e.g. 45 for non resident individuals
+const NIF_TWO_DIGIT_PREFIXES: [&str; 8] = ["45", "70", "71", "72", "74", "75", "77", "79"];
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum NifCheck {
+    Valid,
+    Invalid,
+    GenericConsumer,
+    // Foreign tax numbers do not follow the Portuguese check digit rule
+    Foreign,
+}
+
+impl NifCheck {
+    pub fn is_valid(&self) -> bool {
+        return *self == NifCheck::Valid;
+    }
+}
+
+fn has_valid_prefix(nif: &str) -> bool {
+    return NIF_FIRST_DIGITS.contains(&nif.as_bytes()[0]) || NIF_TWO_DIGIT_PREFIXES.iter().any(|x| nif.starts_with(x));
+}
+
+// Validates the prefix and the mod 11 check digit of a Portuguese NIF
+pub fn is_valid_nif(nif: &str) -> bool {
+    if nif.len() != NIF_LENGTH || !nif.bytes().all(|c| c.is_ascii_digit()) || !has_valid_prefix(nif) {
+        return false;
+    }
+
+    let digits: Vec<u32> = nif.bytes().map(|c| (c - b'0') as u32).collect();
+    let weighted_sum: u32 = digits[..NIF_LENGTH - 1]
+        .iter()
+        .enumerate()
+        .map(|(idx, digit)| digit * (NIF_LENGTH - idx) as u32)
+        .sum();
+
+    let remainder = weighted_sum % 11;
+    let check_digit = if remainder < 2 { 0 } else { 11 - remainder };
+
+    return digits[NIF_LENGTH - 1] == check_digit;
+}
+
+pub fn check_nif(nif: &str) -> NifCheck {
+    if nif == GENERIC_CONSUMER_NIF {
+        return NifCheck::GenericConsumer;
+    }
+    if is_valid_nif(nif) {
+        return NifCheck::Valid;
+    }
+    return NifCheck::Invalid;
+}
+
+// The acquirer NIF is only a Portuguese NIF when the acquirer country (C) is Portugal
+pub fn check_acquirer_nif(nif: &str, country: &str) -> NifCheck {
+    if country != "PT" && nif != GENERIC_CONSUMER_NIF {
+        return NifCheck::Foreign;
+    }
+    return check_nif(nif);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_valid_nifs() {
+        for nif in ["123456789", "500000000", "450000001", "710000006", "200000004", "999999990"] {
+            assert!(is_valid_nif(nif), "{}", nif);
+        }
+    }
+
+    // A check digit of 10 is written as 0, the same as a remainder of 0
+    #[test]
+    fn check_digit_ten_becomes_zero() {
+        assert!(is_valid_nif("200000080"));
+        assert!(!is_valid_nif("200000081"));
+        assert!(is_valid_nif("500000000"));
+    }
+
+    #[test]
+    fn rejects_wrong_check_digit() {
+        for nif in ["123456780", "500000001", "450000002", "999999991"] {
+            assert!(!is_valid_nif(nif), "{}", nif);
+        }
+    }
+
+    // Check digits are right, the prefixes are never assigned
+    #[test]
+    fn rejects_wrong_prefixes() {
+        for nif in ["012345679", "400000008", "730000001"] {
+            assert!(!is_valid_nif(nif), "{}", nif);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_nifs() {
+        for nif in ["", "12345678", "1234567890", "12345678a", " 23456789", "-12345678"] {
+            assert!(!is_valid_nif(nif), "{:?}", nif);
+        }
+    }
+
+    #[test]
+    fn checks_generic_and_foreign_nifs() {
+        assert_eq!(check_nif(GENERIC_CONSUMER_NIF), NifCheck::GenericConsumer);
+        assert_eq!(check_nif("123456789"), NifCheck::Valid);
+        assert_eq!(check_nif("123456780"), NifCheck::Invalid);
+        assert_eq!(check_acquirer_nif("ESB12345678", "ES"), NifCheck::Foreign);
+        assert_eq!(check_acquirer_nif(GENERIC_CONSUMER_NIF, "ES"), NifCheck::GenericConsumer);
+        assert_eq!(check_acquirer_nif("123456780", "PT"), NifCheck::Invalid);
+    }
+}
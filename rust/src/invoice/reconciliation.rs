@@ -0,0 +1,189 @@
+use std::fmt;
+
+use super::invoice_manager::InvoiceMatch;
+use super::match_query::MatchQuery;
+use super::money::Money;
+use crate::bank::BankMovement;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, PartialOrd, Ord)]
+pub enum MatchConfidence {
+    None,
+    // Closest subset is outside the tolerance
+    Low,
+    // Several subsets fit inside the tolerance
+    Medium,
+    // Single subset inside the tolerance
+    High,
+}
+
+impl MatchConfidence {
+    pub fn from_matches(matches: &[InvoiceMatch]) -> MatchConfidence {
+        let within_tolerance = matches.iter().filter(|x| x.within_tolerance).count();
+        match (matches.is_empty(), within_tolerance) {
+            (true, _) => MatchConfidence::None,
+            (false, 0) => MatchConfidence::Low,
+            (false, 1) => MatchConfidence::High,
+            (false, _) => MatchConfidence::Medium,
+        }
+    }
+}
+
+impl fmt::Display for MatchConfidence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MatchConfidence::None => write!(f, "none"),
+            MatchConfidence::Low => write!(f, "low"),
+            MatchConfidence::Medium => write!(f, "medium"),
+            MatchConfidence::High => write!(f, "high"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ReconciliationStatus {
+    Pending,
+    Searching,
+    Proposed,
+    Unmatched,
+    Accepted,
+    Rejected,
+    // The batch was cancelled before this movement was searched
+    Skipped,
+}
+
+// Debit movement of a statement with the subsets of invoices that could pay it
+pub struct ReconciliationRow {
+    pub movement: BankMovement,
+    pub matches: Vec<InvoiceMatch>,
+    pub selected_match: usize,
+    pub confidence: MatchConfidence,
+    pub status: ReconciliationStatus,
+    // Ledger entries created for this movement, more than one when it was split in steps
+    pub assignment_ids: Vec<u64>,
+    // Part of the movement not given to any invoice yet
+    pub open_amount: Money,
+}
+
+impl ReconciliationRow {
+    pub fn new(movement: BankMovement) -> Self {
+        Self {
+            matches: Vec::new(),
+            selected_match: 0,
+            confidence: MatchConfidence::None,
+            status: ReconciliationStatus::Pending,
+            assignment_ids: Vec::new(),
+            open_amount: -movement.amount,
+            movement: movement,
+        }
+    }
+
+    pub fn get_selected_match(&self) -> Option<&InvoiceMatch> {
+        return self.matches.get(self.selected_match);
+    }
+}
+
+// Constraints applied to the search of every movement of a statement
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReconciliationSettings {
+    pub tolerance: Money,
+    // Days before and after the booking date an invoice can be emitted, no date window when None
+    pub date_window: Option<(i64, i64)>,
+    pub max_size: Option<usize>,
+    pub max_candidates: usize,
+}
+
+impl ReconciliationSettings {
+    // Query for the part of the movement still open, positive for debits as the invoices they pay
+    pub fn build_query(&self, movement: &BankMovement, open_amount: Money) -> MatchQuery {
+        let mut query = MatchQuery::new(open_amount).with_tolerance(self.tolerance);
+        if let Some((days_before, days_after)) = self.date_window {
+            query = query.with_date_window(movement.booking_date, days_before, days_after);
+        }
+        if let Some(max_size) = self.max_size {
+            query = query.with_max_size(max_size);
+        }
+        return query;
+    }
+}
+
+// Splits the amount over the open balances in order, the last invoice reached may only be paid in part
+// Credit notes (negative balances) are always taken in full as they add to the amount available
+// The allocations add up to the amount, or to every open balance when it is larger, the rest stays open on the movement
+pub fn allocate_amount(amount: Money, open_balances: &[Money]) -> Vec<Money> {
+    let credits: Money = open_balances.iter().filter(|x| x.is_negative()).map(|x| -*x).sum();
+    let mut remaining = amount + credits;
+
+    return open_balances.iter().map(|balance| {
+        if balance.is_negative() {
+            return *balance;
+        }
+        let allocation = if remaining < *balance { remaining.max(Money::ZERO) } else { *balance };
+        remaining -= allocation;
+        allocation
+    }).collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use super::*;
+
+    fn cents(values: &[i64]) -> Vec<Money> {
+        return values.iter().map(|x| Money::from_cents(*x)).collect();
+    }
+
+    #[test]
+    fn exact_fill_pays_every_balance() {
+        assert_eq!(allocate_amount(Money::from_cents(3000), &cents(&[1000, 2000])), cents(&[1000, 2000]));
+    }
+
+    #[test]
+    fn underpay_is_split_in_order() {
+        assert_eq!(allocate_amount(Money::from_cents(1500), &cents(&[1000, 2000, 500])), cents(&[1000, 500, 0]));
+        assert_eq!(allocate_amount(Money::from_cents(400), &cents(&[1000, 2000])), cents(&[400, 0]));
+    }
+
+    // Invoices are never paid above their balance, the excess stays open on the movement
+    #[test]
+    fn overpay_pays_every_balance_in_full() {
+        assert_eq!(allocate_amount(Money::from_cents(5000), &cents(&[1000, 2000])), cents(&[1000, 2000]));
+    }
+
+    #[test]
+    fn zero_balances_get_nothing() {
+        assert_eq!(allocate_amount(Money::from_cents(1000), &cents(&[0, 1500, 0])), cents(&[0, 1000, 0]));
+        assert_eq!(allocate_amount(Money::ZERO, &cents(&[1000, 2000])), cents(&[0, 0]));
+    }
+
+    // A credit note adds to what the movement pays, so the invoices after it get more than the movement alone
+    #[test]
+    fn credit_notes_are_taken_in_full() {
+        assert_eq!(allocate_amount(Money::from_cents(1500), &cents(&[1000, -500, 1000])), cents(&[1000, -500, 1000]));
+        assert_eq!(allocate_amount(Money::from_cents(1000), &cents(&[1000, -500, 1000])), cents(&[1000, -500, 500]));
+    }
+
+    fn balances() -> impl Strategy<Value = Vec<i64>> {
+        return prop::collection::vec(prop_oneof![4 => 0i64..100_000, 1 => -20_000i64..0], 0..12);
+    }
+
+    proptest! {
+        #[test]
+        fn allocations_add_up_to_the_amount(balances in balances(), fraction in 0.0f64..=1.5) {
+            let open_balances = cents(&balances);
+            let total: Money = open_balances.iter().sum();
+            prop_assume!(total >= Money::ZERO);
+            let amount = Money::from_cents((total.cents() as f64 * fraction) as i64);
+
+            let allocations = allocate_amount(amount, &open_balances);
+            prop_assert_eq!(allocations.len(), open_balances.len());
+            prop_assert_eq!(allocations.iter().sum::<Money>(), amount.min(total));
+            for (allocation, balance) in allocations.iter().zip(open_balances.iter()) {
+                if balance.is_negative() {
+                    prop_assert_eq!(allocation, balance);
+                } else {
+                    prop_assert!(*allocation >= Money::ZERO && allocation <= balance);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use super::InvoiceKey;
+use super::money::Money;
+use crate::bank::BankMovement;
+
+// Part of a movement given to one invoice
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Allocation {
+    pub invoice: InvoiceKey,
+    pub amount: Money,
+}
+
+// Invoices paid, fully or in part, by a bank movement
+// A movement can be split over several assignments and an invoice can be paid by several of them
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Assignment {
+    pub id: u64,
+    pub session: String,
+    pub assigned_at: NaiveDateTime,
+    pub movement: BankMovement,
+    pub invoices: Vec<InvoiceKey>,
+    // Assignments written before partial payments existed have none and settle their invoices in full
+    #[serde(default)]
+    pub allocations: Vec<Allocation>,
+}
+
+impl Assignment {
+    // Amount given to the invoice, invoice_price is used for assignments without allocations
+    pub fn get_allocation(&self, invoice_key: &InvoiceKey, invoice_price: Money) -> Option<Money> {
+        if !self.invoices.contains(invoice_key) {
+            return None;
+        }
+        return Some(self.allocations.iter().find(|x| x.invoice == *invoice_key).map_or(invoice_price, |x| x.amount));
+    }
+
+    // Amount of the movement used by this assignment, positive for the usual debit paying invoices
+    pub fn get_allocated_total(&self) -> Money {
+        if self.allocations.is_empty() {
+            return -self.movement.amount;
+        }
+        return self.allocations.iter().map(|x| x.amount).sum();
+    }
+}
+
+// Reverts a previous assignment, kept as its own entry because the store is append only
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Unassignment {
+    pub session: String,
+    pub undone_at: NaiveDateTime,
+    pub undone_assignment: u64,
+}
+
+// Assignments still in effect, rebuilt by replaying the store entries in order
+#[derive(Debug, Default)]
+pub struct ReconciliationLedger {
+    assignments: Vec<Assignment>,
+    next_id: u64,
+}
+
+impl ReconciliationLedger {
+    pub fn new() -> Self {
+        return ReconciliationLedger::default();
+    }
+
+    pub fn get_next_id(&self) -> u64 {
+        return self.next_id;
+    }
+
+    pub fn apply_assignment(&mut self, assignment: Assignment) {
+        self.next_id = self.next_id.max(assignment.id + 1);
+        self.assignments.push(assignment);
+    }
+
+    pub fn apply_unassignment(&mut self, unassignment: &Unassignment) -> Option<Assignment> {
+        let idx = self.assignments.iter().position(|x| x.id == unassignment.undone_assignment && x.session == unassignment.session)?;
+        return Some(self.assignments.remove(idx));
+    }
+
+    pub fn get_assignments<'a>(&'a self, session: &'a str) -> impl Iterator<Item= &'a Assignment> {
+        return self.assignments.iter().filter(move |x| x.session == session);
+    }
+
+    pub fn get_assignment(&self, session: &str, id: u64) -> Option<&Assignment> {
+        return self.assignments.iter().find(|x| x.session == session && x.id == id);
+    }
+
+    pub fn get_invoice_assignments<'a>(&'a self, session: &'a str, invoice_key: &'a InvoiceKey) -> impl Iterator<Item= &'a Assignment> {
+        return self.get_assignments(session).filter(move |x| x.invoices.contains(invoice_key));
+    }
+
+    pub fn get_movement_assignments<'a>(&'a self, session: &'a str, movement: &'a BankMovement) -> impl Iterator<Item= &'a Assignment> {
+        return self.get_assignments(session).filter(move |x| x.movement == *movement);
+    }
+
+    pub fn get_invoice_allocated(&self, session: &str, invoice_key: &InvoiceKey, invoice_price: Money) -> Money {
+        return self.get_invoice_assignments(session, invoice_key).filter_map(|x| x.get_allocation(invoice_key, invoice_price)).sum();
+    }
+
+    pub fn get_movement_allocated(&self, session: &str, movement: &BankMovement) -> Money {
+        return self.get_movement_assignments(session, movement).map(|x| x.get_allocated_total()).sum();
+    }
+}
@@ -0,0 +1,202 @@
+use crate::invoice::subset_problem::{get_subset_sum, SubsetSolver};
+use crate::invoice::subset_problem::greedy_search::GreedySearchSolver;
+use crate::invoice::subset_problem::search_control::SearchControl;
+use log::warn;
+
+// Maximum number of distinct sums the dynamic programming table can hold (4 bytes each)
+const MAX_DP_RANGE: i64 = 20_000_000;
+// Maximum number of table updates, roughly the number of amounts times the range of sums
+const MAX_DP_OPERATIONS: i64 = 500_000_000;
+// Meet in the middle enumerates 2^(n/2) sums per half
+const MAX_MEET_IN_THE_MIDDLE_LEN: usize = 40;
+
+const UNREACHABLE: u32 = u32::MAX;
+const EMPTY_SUBSET: u32 = u32::MAX - 1;
+// Pairs of half sums tried between two checks of the search control
+const MEET_IN_THE_MIDDLE_CHECK_INTERVAL: usize = 4096;
+
+// Finds a subset that sums exactly to the target whenever one exists, otherwise the subset with the closest sum
+pub struct ExactSearchSolver;
+
+impl ExactSearchSolver {
+    // Dynamic programming over the sums from the credit notes total up to upper_sum, offset so negative amounts fit in the table
+    // Negative amounts go first so every partial sum of a subset is at most its final sum, and sums above upper_sum can be dropped
+    // When stopped early the sums reached with the amounts processed so far are still valid subsets
+    fn solve_dynamic_programming(numbers: &[i64], target: i64, min_sum: i64, upper_sum: i64, control: &SearchControl) -> Vec<usize> {
+        let range = (upper_sum - min_sum + 1) as usize;
+        let offset = -min_sum;
+
+        let mut order: Vec<usize> = (0..numbers.len()).collect();
+        order.sort_by_key(|idx| numbers[*idx] >= 0);
+
+        // Step of the number that first reached each sum, used to walk back the subset
+        let mut reached_by: Vec<u32> = vec![UNREACHABLE; range];
+        reached_by[offset as usize] = EMPTY_SUBSET;
+
+        // Bounds of the sums reached so far, only this window has to be scanned for each number
+        let mut lowest_idx = offset;
+        let mut highest_idx = offset;
+
+        for (step, &idx) in order.iter().enumerate() {
+            if control.should_stop() {
+                warn!("Exact search stopped after {} of {} amounts", step, numbers.len());
+                break;
+            }
+            let number = numbers[idx];
+            if number == 0 {
+                continue;
+            }
+
+            // Iterate away from the shift direction so each number is used at most once
+            let window = (lowest_idx + number)..=(highest_idx + number).min(range as i64 - 1);
+            let sums: Box<dyn Iterator<Item = i64>> = if number > 0 {
+                Box::new(window.rev())
+            } else {
+                Box::new(window)
+            };
+            for sum_idx in sums {
+                let sum_idx = sum_idx as usize;
+                if reached_by[sum_idx] != UNREACHABLE {
+                    continue;
+                }
+                let previous = reached_by[(sum_idx as i64 - number) as usize];
+                if previous != UNREACHABLE && (previous == EMPTY_SUBSET || (previous as usize) < step) {
+                    reached_by[sum_idx] = step as u32;
+                }
+            }
+
+            lowest_idx = lowest_idx.min(lowest_idx + number);
+            highest_idx = highest_idx.max(highest_idx + number).min(range as i64 - 1);
+        }
+
+        // Closest reachable sum to the target, the target itself if possible
+        let target_idx = (target + offset).clamp(0, range as i64 - 1) as usize;
+        let mut best_idx = None;
+        for distance in 0..range {
+            let candidates = [target_idx.checked_sub(distance), target_idx.checked_add(distance).filter(|x| *x < range)];
+            if let Some(found) = candidates.into_iter().flatten().find(|x| reached_by[*x] != UNREACHABLE) {
+                best_idx = Some(found);
+                break;
+            }
+        }
+
+        let mut subset = Vec::new();
+        let mut sum_idx = match best_idx {
+            Some(best_idx) => best_idx,
+            None => return subset,
+        };
+        while reached_by[sum_idx] != EMPTY_SUBSET {
+            let idx = order[reached_by[sum_idx] as usize];
+            subset.push(idx);
+            let number = numbers[idx];
+            sum_idx = (sum_idx as i64 - number) as usize;
+        }
+
+        return subset;
+    }
+
+    fn fits_dynamic_programming(len: usize, min_sum: i64, upper_sum: i64) -> bool {
+        let range = upper_sum - min_sum + 1;
+        return range <= MAX_DP_RANGE && range.saturating_mul(len as i64) <= MAX_DP_OPERATIONS;
+    }
+
+    // Distance of the closest subset a table up to upper_sum can miss, any sum above it is at least this far
+    fn get_distance_above(target: i64, upper_sum: i64) -> i64 {
+        return upper_sum + 1 - target;
+    }
+
+    fn enumerate_subset_sums(numbers: &[i64]) -> Vec<(i64, u64)> {
+        let mut sums = Vec::with_capacity(1 << numbers.len());
+        sums.push((0, 0u64));
+
+        for (idx, number) in numbers.iter().enumerate() {
+            let current_len = sums.len();
+            for i in 0..current_len {
+                let (sum, mask) = sums[i];
+                sums.push((sum + number, mask | (1 << idx)));
+            }
+        }
+
+        return sums;
+    }
+
+    // Splits the numbers in two halves and pairs every sum of the first half with the closest one of the second
+    fn solve_meet_in_the_middle(numbers: &[i64], target: i64, control: &SearchControl) -> Vec<usize> {
+        let (first_half, second_half) = numbers.split_at(numbers.len() / 2);
+        let first_sums = ExactSearchSolver::enumerate_subset_sums(first_half);
+        let mut second_sums = ExactSearchSolver::enumerate_subset_sums(second_half);
+        second_sums.sort_by_key(|x| x.0);
+
+        let mut best: Option<(i64, u64, u64)> = None;
+        for (idx, (first_sum, first_mask)) in first_sums.into_iter().enumerate() {
+            if idx % MEET_IN_THE_MIDDLE_CHECK_INTERVAL == 0 && control.should_stop() {
+                warn!("Exact search stopped, keeping the closest subset found so far");
+                break;
+            }
+
+            let needed = target - first_sum;
+            let pos = second_sums.partition_point(|x| x.0 < needed);
+
+            for candidate in [pos.checked_sub(1), Some(pos)].into_iter().flatten() {
+                if let Some((second_sum, second_mask)) = second_sums.get(candidate) {
+                    let distance = (first_sum + second_sum - target).abs();
+                    if best.map_or(true, |x| distance < x.0) {
+                        best = Some((distance, first_mask, *second_mask));
+                    }
+                }
+            }
+
+            if best.map_or(false, |x| x.0 == 0) {
+                break;
+            }
+        }
+
+        let mut subset = Vec::new();
+        if let Some((_, first_mask, second_mask)) = best {
+            subset.extend((0..first_half.len()).filter(|idx| first_mask & (1 << idx) != 0));
+            subset.extend((0..second_half.len()).filter(|idx| second_mask & (1 << idx) != 0).map(|idx| first_half.len() + idx));
+        }
+        return subset;
+    }
+}
+
+impl SubsetSolver for ExactSearchSolver {
+    fn solve_indices(&self, numbers: &[i64], target: i64, control: &SearchControl) -> Vec<usize> {
+        return self.solve_indices_within(numbers, target, 0, control);
+    }
+
+    // The table only has to reach target + tolerance, a match within the tolerance is never above it
+    // When the closest sum found is further than that, the table is grown once to the furthest sum that can still beat it
+    fn solve_indices_within(&self, numbers: &[i64], target: i64, tolerance: i64, control: &SearchControl) -> Vec<usize> {
+        let min_sum: i64 = numbers.iter().filter(|x| **x < 0).sum();
+        let max_sum: i64 = numbers.iter().filter(|x| **x > 0).sum();
+
+        let mut upper_sum = target.saturating_add(tolerance.abs()).clamp(0, max_sum);
+        let mut bounded_subset = None;
+        if ExactSearchSolver::fits_dynamic_programming(numbers.len(), min_sum, upper_sum) {
+            let subset = ExactSearchSolver::solve_dynamic_programming(numbers, target, min_sum, upper_sum, control);
+            let distance = (get_subset_sum(numbers, &subset) - target).abs();
+            if upper_sum == max_sum || distance <= ExactSearchSolver::get_distance_above(target, upper_sum) || control.should_stop() {
+                return subset;
+            }
+
+            upper_sum = target.saturating_add(distance - 1).clamp(0, max_sum);
+            if ExactSearchSolver::fits_dynamic_programming(numbers.len(), min_sum, upper_sum) {
+                return ExactSearchSolver::solve_dynamic_programming(numbers, target, min_sum, upper_sum, control);
+            }
+            bounded_subset = Some(subset);
+        }
+        if numbers.len() <= MAX_MEET_IN_THE_MIDDLE_LEN {
+            return ExactSearchSolver::solve_meet_in_the_middle(numbers, target, control);
+        }
+
+        warn!("{} amounts adding up to {} cents are too many for an exact search, falling back to the greedy search", numbers.len(), max_sum - min_sum);
+        control.mark_approximate();
+        let greedy_subset = GreedySearchSolver{}.solve_indices(numbers, target, control);
+        // The bounded table is still exact for the sums below its limit, keep it when the greedy search does worse
+        return match bounded_subset {
+            Some(subset) if (get_subset_sum(numbers, &subset) - target).abs() <= (get_subset_sum(numbers, &greedy_subset) - target).abs() => subset,
+            _ => greedy_subset,
+        };
+    }
+}
@@ -1,4 +1,5 @@
 use crate::invoice::subset_problem::SubsetSolver;
+use crate::invoice::subset_problem::search_control::SearchControl;
 
 
 
@@ -6,26 +7,27 @@ pub struct GreedySearchSolver;
 
 
 impl SubsetSolver for GreedySearchSolver{
-    fn solve(&self, numbers: &[i64], target: i64) -> Vec<i64> {
-        let mut sorted_numbers = numbers.to_vec();
-        sorted_numbers.sort(); // Sort the numbers in ascending order
+    fn solve_indices(&self, values: &[i64], target: i64, _control: &SearchControl) -> Vec<usize> {
+        let mut sorted_indices: Vec<usize> = (0..values.len()).collect();
+        sorted_indices.sort_by_key(|x| values[*x]); // Sort the numbers in ascending order
 
-        let mut best_sum = i64::MAX;
-        let mut closest_subset: Vec<i64> = Vec::new();
+        let mut best_distance: Option<i64> = None;
+        let mut closest_subset: Vec<usize> = Vec::new();
 
         // Iterate through the sorted numbers
-        for i in 0..sorted_numbers.len() {
+        for i in 0..sorted_indices.len() {
             let mut current_sum = 0;
-            let mut current_subset: Vec<i64> = Vec::new();
+            let mut current_subset: Vec<usize> = Vec::new();
 
             // Calculate the sum of the current subset
-            for j in i..sorted_numbers.len() {
-                current_sum += sorted_numbers[j];
-                current_subset.push(sorted_numbers[j]);
+            for j in i..sorted_indices.len() {
+                current_sum += values[sorted_indices[j]];
+                current_subset.push(sorted_indices[j]);
 
                 // Update the best sum if the current sum is closer to the target
-                if (current_sum - target).abs() < (best_sum - target).abs() {
-                    best_sum = current_sum;
+                let distance = (current_sum - target).abs();
+                if best_distance.map_or(true, |x| distance < x) {
+                    best_distance = Some(distance);
                     closest_subset = current_subset.clone();
                 }
 
@@ -37,10 +39,6 @@ impl SubsetSolver for GreedySearchSolver{
         }
 
         // Return the closest subset sum if it exists
-        if best_sum != i64::MAX {
-            closest_subset
-        } else {
-            Vec::<i64>::new()
-        }
+        closest_subset
     }
-}
\ No newline at end of file
+}
@@ -1,20 +1,155 @@
-use std::collections::HashMap;
+use std::collections::{HashSet, VecDeque};
+
+use crate::invoice::match_query::MatchQuery;
+use crate::invoice::money::Money;
 
 pub mod greedy_search;
+pub mod exact_search;
+pub mod search_control;
+pub mod search_worker;
+#[cfg(test)]
+mod tests;
+
+use search_control::SearchControl;
+
+// Solvers that can be picked from the UI
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SubsetSolverType {
+    Greedy,
+    Exact,
+}
+
+// Upper bound of solve calls made while looking for alternative subsets, relative to the candidates requested
+const MAX_SOLVE_CALLS_PER_CANDIDATE: usize = 8;
+// Extra candidates looked for when some of them can be dropped for having too many amounts
+const MAX_SIZE_OVERSAMPLING: usize = 4;
 
+// Sum of the values at the given indices, indices out of range are ignored
+pub fn get_subset_sum(values: &[i64], subset: &[usize]) -> i64 {
+    return subset.iter().filter_map(|x| values.get(*x)).sum();
+}
+
+// Picks the elements at the indices of each subset, indices out of range are ignored
+pub fn map_subsets_to_elements<'a, T>(elements: &'a [T], subsets: &[Vec<usize>]) -> Vec<Vec<&'a T>> {
+    return subsets.iter().map(|subset| subset.iter().filter_map(|x| elements.get(*x)).collect()).collect();
+}
+
+// Drops the candidates above the maximum size and puts the ones inside the tolerance first, smaller ones before
+fn rank_query_candidates(values: &[i64], candidates: &[Vec<usize>], query: &MatchQuery, max_candidates: usize) -> Vec<Vec<usize>> {
+    let mut ranked: Vec<Vec<usize>> = candidates
+        .iter()
+        .filter(|x| query.max_size.map_or(true, |max_size| x.len() <= max_size))
+        .cloned()
+        .collect();
+    ranked.sort_by_key(|x| (query.get_excess_distance(Money::from_cents(get_subset_sum(values, x))), x.len()));
+    ranked.truncate(max_candidates);
+    return ranked;
+}
+
+// Solvers answer with indices into the values they are given, so elements with the same value stay distinct
+// and callers can map the answer back to their own elements, or weigh them, without any lookup
 pub trait SubsetSolver {
-    fn solve(&self, numbers: &[i64], target_sum: i64) -> Vec<i64>;
+    // Indices of the values whose sum is closest to the target
+    // Gives up early when the control says so, returning the best subset found until then
+    fn solve_indices(&self, values: &[i64], target_sum: i64, control: &SearchControl) -> Vec<usize>;
+
+    // Same as solve_indices, solvers that can narrow their search to the sums within the tolerance do so
+    fn solve_indices_within(&self, values: &[i64], target_sum: i64, _tolerance: i64, control: &SearchControl) -> Vec<usize> {
+        return self.solve_indices(values, target_sum, control);
+    }
+
+    fn solve(&self, numbers: &[i64], target_sum: i64) -> Vec<i64> {
+        let subset = self.solve_indices(numbers, target_sum, &SearchControl::unbounded());
+        return subset.iter().filter_map(|x| numbers.get(*x)).copied().collect();
+    }
+
+    fn solve_vector<'a, T, F: Fn(&T) -> i64>(&self, elements: &'a [T], target_sum: i64, value_get: F) -> Vec<&'a T> {
+        let values: Vec<i64> = elements.iter().map(|x| value_get(x)).collect();
+        let subset = self.solve_indices(&values, target_sum, &SearchControl::unbounded());
+        return subset.iter().filter_map(|x| elements.get(*x)).collect();
+    }
+
+    // Finds up to max_candidates distinct index sets by solving again with each member of a previous answer left out
+    // on_progress receives the candidates found so far and the fraction of the search done after every solve
+    fn solve_candidates(&self, values: &[i64], target_sum: i64, max_candidates: usize,
+        control: &SearchControl, on_progress: &mut dyn FnMut(&[Vec<usize>], f32)) -> Vec<Vec<usize>> {
+        return self.solve_candidates_within(values, target_sum, 0, max_candidates, control, on_progress);
+    }
 
-    fn solve_vector<'a,T>(&self, elements: &'a[&T], target_sum : i64, value_get:fn(&'a T) -> i64) -> Vec<&'a T> {
-        let numbers: Vec<i64> = elements.iter().map(|x| value_get(x)).collect();
-        let mut elements_map: HashMap<i64, Vec<&'a T>> = HashMap::new();
+    fn solve_candidates_within(&self, values: &[i64], target_sum: i64, tolerance: i64, max_candidates: usize,
+        control: &SearchControl, on_progress: &mut dyn FnMut(&[Vec<usize>], f32)) -> Vec<Vec<usize>> {
 
-        for element in elements{
-            elements_map.entry(value_get(element)).or_insert_with(|| Vec::with_capacity(1)).push(element);
+        let mut candidates: Vec<Vec<usize>> = Vec::new();
+        let mut visited_pools: HashSet<Vec<usize>> = HashSet::new();
+        // Indices of the values each solve can still use
+        let mut pools: VecDeque<Vec<usize>> = VecDeque::new();
+        pools.push_back((0..values.len()).collect());
+
+        let max_solve_calls = max_candidates * MAX_SOLVE_CALLS_PER_CANDIDATE;
+        let mut solve_calls = 0;
+        while let Some(pool) = pools.pop_front() {
+            if candidates.len() >= max_candidates || solve_calls >= max_solve_calls || control.should_stop() {
+                break;
+            }
+            if !visited_pools.insert(pool.clone()) {
+                continue;
+            }
+
+            let pool_values: Vec<i64> = pool.iter().map(|x| values[*x]).collect();
+            let mut subset: Vec<usize> = self.solve_indices_within(&pool_values, target_sum, tolerance, control)
+                .iter()
+                .filter_map(|x| pool.get(*x))
+                .copied()
+                .collect();
+            subset.sort();
+            subset.dedup();
+            solve_calls += 1;
+
+            if !subset.is_empty() {
+                for idx in subset.iter() {
+                    pools.push_back(pool.iter().filter(|x| *x != idx).copied().collect());
+                }
+
+                if !candidates.contains(&subset) {
+                    candidates.push(subset);
+                }
+            }
+
+            let progress = (candidates.len() as f32 / max_candidates as f32).max(solve_calls as f32 / max_solve_calls as f32);
+            on_progress(&candidates, progress.min(1.0));
+        }
+
+        return candidates;
+    }
+
+    fn solve_vector_candidates<'a, T, F: Fn(&T) -> i64>(&self, elements: &'a [T], target_sum: i64, max_candidates: usize, value_get: F) -> Vec<Vec<&'a T>> {
+        let values: Vec<i64> = elements.iter().map(|x| value_get(x)).collect();
+        let candidates = self.solve_candidates(&values, target_sum, max_candidates, &SearchControl::unbounded(), &mut |_, _| {});
+        return map_subsets_to_elements(elements, &candidates);
+    }
+
+    // Candidates for the query target, honouring its maximum size and ranked with its tolerance
+    // Filters and must include elements are applied by the caller before solving
+    fn solve_query_candidates(&self, values: &[i64], query: &MatchQuery, max_candidates: usize,
+        control: &SearchControl, on_progress: &mut dyn FnMut(&[Vec<usize>], f32)) -> Vec<Vec<usize>> {
+
+        if query.max_size == Some(0) {
+            return vec![Vec::new()];
         }
 
-        //Get first elemnt in map of vec and remove it
-        let subset : Vec<i64> = self.solve(&numbers, target_sum);
-        return subset.iter().map(move |x| elements_map.get_mut(x).unwrap().pop().unwrap()).collect();
+        let requested_candidates = match query.max_size {
+            Some(_) => max_candidates * MAX_SIZE_OVERSAMPLING,
+            None => max_candidates,
+        };
+        let candidates = self.solve_candidates_within(values, query.target.cents(), query.tolerance.cents(), requested_candidates, control, &mut |candidates, progress| {
+            on_progress(&rank_query_candidates(values, candidates, query, max_candidates), progress);
+        });
+        return rank_query_candidates(values, &candidates, query, max_candidates);
+    }
+
+    fn solve_vector_query<'a, T, F: Fn(&T) -> i64>(&self, elements: &'a [T], query: &MatchQuery, max_candidates: usize, value_get: F) -> Vec<Vec<&'a T>> {
+        let values: Vec<i64> = elements.iter().map(|x| value_get(x)).collect();
+        let candidates = self.solve_query_candidates(&values, query, max_candidates, &SearchControl::unbounded(), &mut |_, _| {});
+        return map_subsets_to_elements(elements, &candidates);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,69 @@
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SearchStopReason {
+    Cancelled,
+    DeadlineReached,
+}
+
+impl fmt::Display for SearchStopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SearchStopReason::Cancelled => write!(f, "cancelled"),
+            SearchStopReason::DeadlineReached => write!(f, "deadline reached"),
+        }
+    }
+}
+
+// Cancellation token and deadline shared between a search and whoever started it
+// Solvers check it between steps and return the best subset found so far once it says to stop
+#[derive(Debug, Clone)]
+pub struct SearchControl {
+    cancelled: Arc<AtomicBool>,
+    // Set by solvers that had to answer with a heuristic instead of the search they are meant to do
+    approximate: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+impl SearchControl {
+    pub fn new(timeout: Option<Duration>) -> Self {
+        Self {
+            cancelled: Arc::new(AtomicBool::new(false)),
+            approximate: Arc::new(AtomicBool::new(false)),
+            deadline: timeout.map(|x| Instant::now() + x),
+        }
+    }
+
+    pub fn unbounded() -> Self {
+        return SearchControl::new(None);
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn mark_approximate(&self) {
+        self.approximate.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_approximate(&self) -> bool {
+        return self.approximate.load(Ordering::Relaxed);
+    }
+
+    pub fn get_stop_reason(&self) -> Option<SearchStopReason> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            return Some(SearchStopReason::Cancelled);
+        }
+        if self.deadline.map_or(false, |x| Instant::now() >= x) {
+            return Some(SearchStopReason::DeadlineReached);
+        }
+        return None;
+    }
+
+    pub fn should_stop(&self) -> bool {
+        return self.get_stop_reason().is_some();
+    }
+}
@@ -0,0 +1,104 @@
+use std::sync::mpsc;
+use std::time::Duration;
+
+use log::{debug, error, info};
+
+use crate::invoice::match_query::MatchQuery;
+use crate::invoice::subset_problem::{SubsetSolver, SubsetSolverType};
+use crate::invoice::subset_problem::exact_search::ExactSearchSolver;
+use crate::invoice::subset_problem::greedy_search::GreedySearchSolver;
+use crate::invoice::subset_problem::search_control::{SearchControl, SearchStopReason};
+
+// Messages sent by the search thread, subsets are given as indices into the amounts searched
+#[derive(Debug, Clone, PartialEq)]
+pub enum SearchUpdate {
+    Progress {
+        candidates: Vec<Vec<usize>>,
+        progress: f32,
+    },
+    Finished {
+        candidates: Vec<Vec<usize>>,
+        stop_reason: Option<SearchStopReason>,
+        // Some solve fell back to a heuristic, the candidates may not be the best ones
+        approximate: bool,
+    },
+}
+
+// Subset search running on its own thread, dropping it cancels the search
+pub struct SubsetSearchWorker {
+    control: SearchControl,
+    update_recv: mpsc::Receiver<SearchUpdate>,
+    finished: bool,
+}
+
+impl SubsetSearchWorker {
+    pub fn start(solver_type: SubsetSolverType, numbers: Vec<i64>, query: MatchQuery, max_candidates: usize, timeout: Option<Duration>) -> Self {
+        let control = SearchControl::new(timeout);
+        let (update_sender, update_recv) = mpsc::channel::<SearchUpdate>();
+
+        let thread_control = control.clone();
+        std::thread::spawn(move || {
+            debug!("Starting {:?} subset search over {} amounts", solver_type, numbers.len());
+            // The receiver may be gone if the search was dropped, there is nobody left to tell
+            let mut on_progress = |candidates: &[Vec<usize>], progress: f32| {
+                let _ = update_sender.send(SearchUpdate::Progress { candidates: candidates.to_vec(), progress: progress });
+            };
+
+            let candidates = match solver_type {
+                SubsetSolverType::Greedy => GreedySearchSolver{}.solve_query_candidates(&numbers, &query, max_candidates, &thread_control, &mut on_progress),
+                SubsetSolverType::Exact => ExactSearchSolver{}.solve_query_candidates(&numbers, &query, max_candidates, &thread_control, &mut on_progress),
+            };
+
+            let stop_reason = thread_control.get_stop_reason();
+            match stop_reason {
+                Some(stop_reason) => info!("Subset search stopped ({}) with {} candidates", stop_reason, candidates.len()),
+                None => info!("Subset search finished with {} candidates", candidates.len()),
+            }
+            let approximate = thread_control.is_approximate();
+            let _ = update_sender.send(SearchUpdate::Finished { candidates: candidates, stop_reason: stop_reason, approximate: approximate });
+        });
+
+        Self {
+            control: control,
+            update_recv: update_recv,
+            finished: false,
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.control.cancel();
+    }
+
+    pub fn is_finished(&self) -> bool {
+        return self.finished;
+    }
+
+    // Latest update sent since the last call, if any
+    pub fn check_updates(&mut self) -> Option<SearchUpdate> {
+        let mut update = None;
+        loop {
+            match self.update_recv.try_recv() {
+                Ok(received) => update = Some(received),
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    if !self.finished && !matches!(update, Some(SearchUpdate::Finished { .. })) {
+                        error!("Subset search thread exited without a result");
+                        self.finished = true;
+                    }
+                    break;
+                }
+            }
+        }
+
+        if let Some(SearchUpdate::Finished { .. }) = update {
+            self.finished = true;
+        }
+        return update;
+    }
+}
+
+impl Drop for SubsetSearchWorker {
+    fn drop(&mut self) {
+        self.control.cancel();
+    }
+}
@@ -0,0 +1,207 @@
+use proptest::prelude::*;
+
+use crate::invoice::match_query::MatchQuery;
+use crate::invoice::money::Money;
+use crate::invoice::subset_problem::{get_subset_sum, SubsetSolver};
+use crate::invoice::subset_problem::exact_search::ExactSearchSolver;
+use crate::invoice::subset_problem::greedy_search::GreedySearchSolver;
+use crate::invoice::subset_problem::search_control::SearchControl;
+
+// Brute force is 2^n, keep the inputs small enough for every case to run in microseconds
+const MAX_ORACLE_LEN: usize = 12;
+// Invoices up to 200€, larger ones only make the dynamic programming table slower to fill
+const MAX_AMOUNT: i64 = 20_000;
+// Searching for candidates solves many times per case
+const CANDIDATE_CASES: u32 = 64;
+// Far more amounts than the oracle or meet in the middle can handle
+const LARGE_INPUT_LEN: usize = 1000;
+
+// Smallest distance to the target over every subset, the empty one included
+fn oracle_distance(values: &[i64], target: i64) -> i64 {
+    let mut best = target.abs();
+    for mask in 1u32..(1 << values.len()) {
+        let sum: i64 = (0..values.len()).filter(|idx| mask & (1 << idx) != 0).map(|idx| values[idx]).sum();
+        best = best.min((sum - target).abs());
+    }
+    return best;
+}
+
+fn is_valid_index_set(values: &[i64], subset: &[usize]) -> bool {
+    let mut sorted = subset.to_vec();
+    sorted.sort();
+    sorted.dedup();
+    return sorted.len() == subset.len() && subset.iter().all(|x| *x < values.len());
+}
+
+// Amounts in cents, negative ones are credit notes
+fn amounts(max_amount: i64) -> impl Strategy<Value = Vec<i64>> {
+    return prop::collection::vec(-max_amount / 4..=max_amount, 0..=MAX_ORACLE_LEN);
+}
+
+// Few distinct amounts so most inputs repeat some of them
+fn amounts_with_duplicates() -> impl Strategy<Value = Vec<i64>> {
+    return prop::collection::vec(prop::sample::select(vec![-1500, 990, 1230, 1230, 2460, 5000]), 0..=MAX_ORACLE_LEN);
+}
+
+// Target made of a random subset of the amounts, so an exact answer always exists
+fn reachable_target(values: &[i64], mask: u32) -> i64 {
+    return values.iter().enumerate().filter(|(idx, _)| mask & (1 << idx) != 0).map(|(_, x)| *x).sum();
+}
+
+fn check_exact_solver(values: &[i64], target: i64) -> Result<(), TestCaseError> {
+    let subset = ExactSearchSolver{}.solve_indices(values, target, &SearchControl::unbounded());
+    prop_assert!(is_valid_index_set(values, &subset), "invalid index set {:?} for {:?}", subset, values);
+    prop_assert_eq!((get_subset_sum(values, &subset) - target).abs(), oracle_distance(values, target));
+    return Ok(());
+}
+
+// The greedy search is a heuristic, it only has to give a valid subset that is never better than the optimum
+fn check_greedy_solver(values: &[i64], target: i64) -> Result<(), TestCaseError> {
+    let subset = GreedySearchSolver{}.solve_indices(values, target, &SearchControl::unbounded());
+    prop_assert!(is_valid_index_set(values, &subset), "invalid index set {:?} for {:?}", subset, values);
+    prop_assert!((get_subset_sum(values, &subset) - target).abs() >= oracle_distance(values, target));
+    return Ok(());
+}
+
+fn check_candidates<S: SubsetSolver>(solver: &S, values: &[i64], target: i64, max_candidates: usize) -> Result<(), TestCaseError> {
+    let candidates = solver.solve_candidates(values, target, max_candidates, &SearchControl::unbounded(), &mut |_, _| {});
+    prop_assert!(candidates.len() <= max_candidates);
+    for (idx, candidate) in candidates.iter().enumerate() {
+        prop_assert!(is_valid_index_set(values, candidate), "invalid index set {:?} for {:?}", candidate, values);
+        prop_assert!(!candidates[..idx].contains(candidate), "repeated candidate {:?}", candidate);
+    }
+    return Ok(());
+}
+
+// Xorshift amounts between min and max cents, the same ones on every run
+fn large_amounts(seed: u64, min: i64, max: i64) -> Vec<i64> {
+    let mut state = seed;
+    return (0..LARGE_INPUT_LEN).map(|_| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        min + (state % (max - min + 1) as u64) as i64
+    }).collect();
+}
+
+// Invoices up to 1000€, a few small credit notes and a target paid by a few invoices fit in the table sized by the target
+#[test]
+fn exact_stays_exact_on_large_input() {
+    let values = large_amounts(0x5eed, -5_000, 100_000);
+    let target = values[3] + values[250] + values[500] + values[999];
+    let control = SearchControl::unbounded();
+    let subset = ExactSearchSolver{}.solve_indices_within(&values, target, 100, &control);
+    assert!(is_valid_index_set(&values, &subset));
+    assert_eq!(get_subset_sum(&values, &subset), target);
+    assert!(!control.is_approximate());
+}
+
+// Amounts whose sums can not fit in the table fall back to the greedy search and say so
+#[test]
+fn exact_reports_fallback_on_large_amounts() {
+    let values = large_amounts(0xfa11, 1_000_000, 1_000_000_000);
+    let target = values[10] + values[20] + values[30];
+    let control = SearchControl::unbounded();
+    let subset = ExactSearchSolver{}.solve_indices(&values, target, &control);
+    assert!(is_valid_index_set(&values, &subset));
+    assert!(control.is_approximate());
+
+    // Inputs small enough for an exact search never report a fallback
+    let control = SearchControl::unbounded();
+    ExactSearchSolver{}.solve_indices(&values[..MAX_ORACLE_LEN], target, &control);
+    assert!(!control.is_approximate());
+}
+
+proptest! {
+    #[test]
+    fn exact_matches_oracle(values in amounts(MAX_AMOUNT), target in -MAX_AMOUNT..MAX_AMOUNT * 6) {
+        check_exact_solver(&values, target)?;
+    }
+
+    // Amounts too large for the dynamic programming table go through meet in the middle
+    #[test]
+    fn exact_matches_oracle_large_amounts(values in amounts(1_000_000_000), target in -500_000_000i64..6_000_000_000) {
+        check_exact_solver(&values, target)?;
+    }
+
+    #[test]
+    fn exact_matches_oracle_with_duplicates(values in amounts_with_duplicates(), target in -3000i64..30_000) {
+        check_exact_solver(&values, target)?;
+    }
+
+    // The table sized by the tolerance has to grow when the closest sum is above it
+    #[test]
+    fn exact_within_tolerance_matches_oracle(values in amounts(MAX_AMOUNT), target in -MAX_AMOUNT..MAX_AMOUNT * 6, tolerance in 0i64..2000) {
+        let subset = ExactSearchSolver{}.solve_indices_within(&values, target, tolerance, &SearchControl::unbounded());
+        prop_assert!(is_valid_index_set(&values, &subset), "invalid index set {:?} for {:?}", subset, values);
+        prop_assert_eq!((get_subset_sum(&values, &subset) - target).abs(), oracle_distance(&values, target));
+    }
+
+    #[test]
+    fn exact_hits_reachable_target(values in amounts(MAX_AMOUNT), mask in any::<u32>()) {
+        let target = reachable_target(&values, mask);
+        let subset = ExactSearchSolver{}.solve_indices(&values, target, &SearchControl::unbounded());
+        prop_assert_eq!(get_subset_sum(&values, &subset), target);
+    }
+
+    // Targets beyond every possible sum must give the closest one instead of failing
+    #[test]
+    fn exact_handles_unreachable_target(values in amounts(MAX_AMOUNT), excess in 1i64..1_000_000) {
+        let max_sum: i64 = values.iter().filter(|x| **x > 0).sum();
+        let min_sum: i64 = values.iter().filter(|x| **x < 0).sum();
+        check_exact_solver(&values, max_sum + excess)?;
+        check_exact_solver(&values, min_sum - excess)?;
+    }
+
+    #[test]
+    fn greedy_never_beats_oracle(values in amounts(MAX_AMOUNT), target in -MAX_AMOUNT..MAX_AMOUNT * 6) {
+        check_greedy_solver(&values, target)?;
+    }
+
+    #[test]
+    fn greedy_never_beats_oracle_with_duplicates(values in amounts_with_duplicates(), target in -3000i64..30_000) {
+        check_greedy_solver(&values, target)?;
+    }
+
+    // A cancelled search still has to answer with a valid subset
+    #[test]
+    fn cancelled_search_returns_valid_subset(values in amounts(MAX_AMOUNT), target in -MAX_AMOUNT..MAX_AMOUNT * 6) {
+        let control = SearchControl::unbounded();
+        control.cancel();
+        let subset = ExactSearchSolver{}.solve_indices(&values, target, &control);
+        prop_assert!(is_valid_index_set(&values, &subset));
+        let candidates = ExactSearchSolver{}.solve_candidates(&values, target, 5, &control, &mut |_, _| {});
+        prop_assert!(candidates.is_empty());
+    }
+
+    #[test]
+    fn solve_vector_maps_indices_to_elements(values in amounts_with_duplicates(), target in -3000i64..30_000) {
+        let elements: Vec<(usize, i64)> = values.iter().copied().enumerate().collect();
+        let subset = ExactSearchSolver{}.solve_vector(&elements, target, |x| x.1);
+        let mut positions: Vec<usize> = subset.iter().map(|x| x.0).collect();
+        positions.sort();
+        positions.dedup();
+        prop_assert_eq!(positions.len(), subset.len());
+        prop_assert_eq!((subset.iter().map(|x| x.1).sum::<i64>() - target).abs(), oracle_distance(&values, target));
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(CANDIDATE_CASES))]
+
+    #[test]
+    fn candidates_are_distinct_index_sets(values in amounts_with_duplicates(), target in 0i64..30_000, max_candidates in 1usize..6) {
+        check_candidates(&ExactSearchSolver{}, &values, target, max_candidates)?;
+        check_candidates(&GreedySearchSolver{}, &values, target, max_candidates)?;
+    }
+
+    #[test]
+    fn query_candidates_respect_max_size(values in amounts(MAX_AMOUNT), target in 0..MAX_AMOUNT * 6, max_size in 0usize..4) {
+        let query = MatchQuery::new(Money::from_cents(target)).with_max_size(max_size);
+        let candidates = ExactSearchSolver{}.solve_query_candidates(&values, &query, 5, &SearchControl::unbounded(), &mut |_, _| {});
+        for candidate in candidates.iter() {
+            prop_assert!(candidate.len() <= max_size);
+            prop_assert!(is_valid_index_set(&values, candidate));
+        }
+    }
+}
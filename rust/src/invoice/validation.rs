@@ -0,0 +1,202 @@
+use std::fmt;
+use chrono::NaiveDate;
+
+use super::invoice_qr::InvoiceQRFields;
+use super::money::Money;
+use super::nif::NifCheck;
+
+// Allowed difference between a declared total and the one computed from its parts, to absorb per line rounding
+pub const ROUNDING_TOLERANCE: Money = Money::from_cents(1);
+
+// Minimum length of the validation code part of the ATCUD
+const ATCUD_VALIDATION_CODE_MIN_LENGTH: usize = 8;
+// Documents the ATCUD does not apply to have it set to 0
+const ATCUD_NOT_APPLICABLE: &str = "0";
+
+// Issue found on an invoice that was parsed successfully but should be reviewed before being booked
+#[derive(Debug, Clone, PartialEq)]
+pub enum InvoiceWarning {
+    InvalidNif { field: String, nif: String, check: NifCheck },
+    TotalTaxMismatch { declared: Money, computed: Money },
+    GrossTotalMismatch { declared: Money, computed: Money },
+    MalformedAtcud(String),
+    FutureEmissionDate(NaiveDate),
+}
+
+impl fmt::Display for InvoiceWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvoiceWarning::InvalidNif { field, nif, check: NifCheck::GenericConsumer } => write!(f, "Field {} has the generic consumer NIF {}", field, nif),
+            InvoiceWarning::InvalidNif { field, nif, .. } => write!(f, "Field {} has the invalid NIF {}", field, nif),
+            InvoiceWarning::TotalTaxMismatch { declared, computed } => write!(f, "Total tax (N) is {} but VAT plus stamp tax add up to {}", declared, computed),
+            InvoiceWarning::GrossTotalMismatch { declared, computed } => write!(f, "Gross total (O) is {} but bases plus taxes add up to {}", declared, computed),
+            InvoiceWarning::MalformedAtcud(atcud) => write!(f, "ATCUD '{}' is not in the <validation code>-<sequence> format", atcud),
+            InvoiceWarning::FutureEmissionDate(date) => write!(f, "Emission date {} is in the future", date),
+        }
+    }
+}
+
+fn is_within_tolerance(declared: Money, computed: Money) -> bool {
+    return (declared - computed).abs() <= ROUNDING_TOLERANCE;
+}
+
+pub fn is_valid_atcud(atcud: &str) -> bool {
+    if atcud == ATCUD_NOT_APPLICABLE {
+        return true;
+    }
+    let (validation_code, sequence) = match atcud.split_once('-') {
+        Some(parts) => parts,
+        None => return false,
+    };
+
+    return validation_code.len() >= ATCUD_VALIDATION_CODE_MIN_LENGTH
+        && validation_code.bytes().all(|c| c.is_ascii_alphanumeric())
+        && !sequence.is_empty()
+        && sequence.bytes().all(|c| c.is_ascii_digit());
+}
+
+// Runs every cross field consistency check over a parsed invoice
+pub fn validate_invoice(fields: &InvoiceQRFields, issuer_nif_check: NifCheck, acquirer_nif_check: NifCheck, today: NaiveDate) -> Vec<InvoiceWarning> {
+    let mut warnings = Vec::new();
+
+    if issuer_nif_check != NifCheck::Valid {
+        warnings.push(InvoiceWarning::InvalidNif{field: "A".to_string(), nif: fields.issuer_nif.clone(), check: issuer_nif_check});
+    }
+    if matches!(acquirer_nif_check, NifCheck::Invalid | NifCheck::GenericConsumer) {
+        warnings.push(InvoiceWarning::InvalidNif{field: "B".to_string(), nif: fields.acquirer_nif.clone(), check: acquirer_nif_check});
+    }
+
+    let computed_tax = fields.total_vat() + fields.stamp_tax.unwrap_or_default();
+    if !is_within_tolerance(fields.total_tax, computed_tax) {
+        warnings.push(InvoiceWarning::TotalTaxMismatch{declared: fields.total_tax, computed: computed_tax});
+    }
+
+    // Some issuers deduct the withholding tax from the gross total and others do not, both are accepted
+    let computed_gross = fields.total_taxable_base() + fields.non_taxable.unwrap_or_default() + fields.total_tax;
+    let withholding_tax = fields.withholding_tax.unwrap_or_default();
+    if !is_within_tolerance(fields.total_price, computed_gross - withholding_tax) && !is_within_tolerance(fields.total_price, computed_gross) {
+        warnings.push(InvoiceWarning::GrossTotalMismatch{declared: fields.total_price, computed: computed_gross - withholding_tax});
+    }
+
+    if !is_valid_atcud(&fields.atcud) {
+        warnings.push(InvoiceWarning::MalformedAtcud(fields.atcud.clone()));
+    }
+
+    if fields.emission_date > today {
+        warnings.push(InvoiceWarning::FutureEmissionDate(fields.emission_date));
+    }
+
+    return warnings;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::invoice::document::{DocumentStatus, DocumentType};
+    use crate::invoice::vat::{FiscalSpace, VatBreakdown, VatLine};
+
+    fn today() -> NaiveDate {
+        return NaiveDate::from_ymd_opt(2024, 3, 15).unwrap();
+    }
+
+    // 100€ at 23% VAT, consistent in every field
+    fn consistent_fields() -> InvoiceQRFields {
+        let mut breakdown = VatBreakdown::new(FiscalSpace::Portugal);
+        breakdown.normal = Some(VatLine{taxable_base: Money::from_cents(10000), vat: Money::from_cents(2300)});
+        return InvoiceQRFields {
+            issuer_nif: "123456789".to_string(),
+            acquirer_nif: "200000004".to_string(),
+            acquirer_country: "PT".to_string(),
+            document_type: DocumentType::Invoice,
+            document_status: DocumentStatus::Normal,
+            emission_date: NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(),
+            invoice_number: "FT 2024/1".to_string(),
+            atcud: "CSDF7T5H-1".to_string(),
+            vat_breakdowns: [Some(breakdown), None, None],
+            non_taxable: None,
+            stamp_tax: None,
+            total_tax: Money::from_cents(2300),
+            total_price: Money::from_cents(12300),
+            withholding_tax: None,
+            hash: "abcd".to_string(),
+            certificate_number: 1234,
+            other_info: None,
+        };
+    }
+
+    fn validate(fields: &InvoiceQRFields) -> Vec<InvoiceWarning> {
+        return validate_invoice(fields, NifCheck::Valid, NifCheck::Valid, today());
+    }
+
+    #[test]
+    fn consistent_invoice_has_no_warnings() {
+        assert_eq!(validate(&consistent_fields()), Vec::new());
+    }
+
+    #[test]
+    fn warns_on_invalid_nifs() {
+        let fields = consistent_fields();
+        let warnings = validate_invoice(&fields, NifCheck::Invalid, NifCheck::GenericConsumer, today());
+        assert_eq!(warnings, vec![
+            InvoiceWarning::InvalidNif{field: "A".to_string(), nif: fields.issuer_nif.clone(), check: NifCheck::Invalid},
+            InvoiceWarning::InvalidNif{field: "B".to_string(), nif: fields.acquirer_nif.clone(), check: NifCheck::GenericConsumer},
+        ]);
+        // Foreign acquirers can not be checked, they are not a warning
+        assert_eq!(validate_invoice(&fields, NifCheck::Valid, NifCheck::Foreign, today()), Vec::new());
+    }
+
+    #[test]
+    fn warns_on_total_tax_mismatch() {
+        let mut fields = consistent_fields();
+        fields.total_tax = Money::from_cents(2400);
+        fields.total_price = Money::from_cents(12400);
+        assert_eq!(validate(&fields), vec![InvoiceWarning::TotalTaxMismatch{declared: Money::from_cents(2400), computed: Money::from_cents(2300)}]);
+
+        // Stamp tax is part of the total tax
+        fields.stamp_tax = Some(Money::from_cents(100));
+        assert_eq!(validate(&fields), Vec::new());
+    }
+
+    #[test]
+    fn warns_on_gross_total_mismatch() {
+        let mut fields = consistent_fields();
+        fields.total_price = Money::from_cents(12500);
+        assert_eq!(validate(&fields), vec![InvoiceWarning::GrossTotalMismatch{declared: Money::from_cents(12500), computed: Money::from_cents(12300)}]);
+    }
+
+    #[test]
+    fn accepts_rounding_and_withholding_tax() {
+        let mut fields = consistent_fields();
+        fields.total_price = Money::from_cents(12301);
+        assert_eq!(validate(&fields), Vec::new());
+
+        fields.withholding_tax = Some(Money::from_cents(2500));
+        fields.total_price = Money::from_cents(9800);
+        assert_eq!(validate(&fields), Vec::new());
+        fields.total_price = Money::from_cents(12300);
+        assert_eq!(validate(&fields), Vec::new());
+    }
+
+    #[test]
+    fn warns_on_malformed_atcud() {
+        for atcud in ["CSDF7T5H", "SHORT-1", "CSDF7T5H-", "CSDF7T5H-1A", "CSDF-7T5H-1"] {
+            let mut fields = consistent_fields();
+            fields.atcud = atcud.to_string();
+            assert_eq!(validate(&fields), vec![InvoiceWarning::MalformedAtcud(atcud.to_string())], "{}", atcud);
+        }
+
+        let mut fields = consistent_fields();
+        fields.atcud = "0".to_string();
+        assert_eq!(validate(&fields), Vec::new());
+    }
+
+    #[test]
+    fn warns_on_future_emission_date() {
+        let mut fields = consistent_fields();
+        fields.emission_date = today().succ_opt().unwrap();
+        assert_eq!(validate(&fields), vec![InvoiceWarning::FutureEmissionDate(fields.emission_date)]);
+
+        fields.emission_date = today();
+        assert_eq!(validate(&fields), Vec::new());
+    }
+}
@@ -0,0 +1,96 @@
+use std::fmt;
+use std::str::FromStr;
+use super::money::Money;
+
+// Fiscal space a VAT block refers to (fields I1, J1 and K1)
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum FiscalSpace {
+    Portugal,
+    Azores,
+    Madeira,
+}
+
+impl FromStr for FiscalSpace {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "PT" => Ok(FiscalSpace::Portugal),
+            "PT-AC" => Ok(FiscalSpace::Azores),
+            "PT-MA" => Ok(FiscalSpace::Madeira),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for FiscalSpace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let code = match self {
+            FiscalSpace::Portugal => "PT",
+            FiscalSpace::Azores => "PT-AC",
+            FiscalSpace::Madeira => "PT-MA",
+        };
+        write!(f, "{}", code)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum VatRateTier {
+    Exempt,
+    Reduced,
+    Intermediate,
+    Normal,
+}
+
+impl VatRateTier {
+    pub const ALL: [VatRateTier; 4] = [VatRateTier::Exempt, VatRateTier::Reduced, VatRateTier::Intermediate, VatRateTier::Normal];
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct VatLine {
+    pub taxable_base: Money,
+    pub vat: Money,
+}
+
+// VAT of a single fiscal space split by rate tier, a tier is None when it is not present on the QR code
+#[derive(Debug, PartialEq, Clone)]
+pub struct VatBreakdown {
+    pub fiscal_space: FiscalSpace,
+    pub exempt: Option<VatLine>,
+    pub reduced: Option<VatLine>,
+    pub intermediate: Option<VatLine>,
+    pub normal: Option<VatLine>,
+}
+
+impl VatBreakdown {
+    pub fn new(fiscal_space: FiscalSpace) -> Self {
+        Self {
+            fiscal_space: fiscal_space,
+            exempt: None,
+            reduced: None,
+            intermediate: None,
+            normal: None,
+        }
+    }
+
+    pub fn get_line(&self, tier: VatRateTier) -> Option<&VatLine> {
+        match tier {
+            VatRateTier::Exempt => self.exempt.as_ref(),
+            VatRateTier::Reduced => self.reduced.as_ref(),
+            VatRateTier::Intermediate => self.intermediate.as_ref(),
+            VatRateTier::Normal => self.normal.as_ref(),
+        }
+    }
+
+    pub fn lines(&self) -> impl Iterator<Item = (VatRateTier, &VatLine)> {
+        VatRateTier::ALL.into_iter().filter_map(move |tier| self.get_line(tier).map(|line| (tier, line)))
+    }
+
+    pub fn total_taxable_base(&self) -> Money {
+        return self.lines().map(|(_, line)| line.taxable_base).sum();
+    }
+
+    pub fn total_vat(&self) -> Money {
+        return self.lines().map(|(_, line)| line.vat).sum();
+    }
+}
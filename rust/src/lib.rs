@@ -0,0 +1,5 @@
+// Invoice handling without the camera pipeline and the UI, so tests and benchmarks can use it
+pub mod qr_code;
+pub mod invoice;
+pub mod export;
+pub mod bank;
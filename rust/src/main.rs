@@ -2,10 +2,9 @@
 
 use eframe::egui;
 
-pub mod qr_code;
-pub mod invoice;
 pub mod cv_worker;
 pub mod constants;
+use pt_invoice_automation::{qr_code, invoice, export, bank};
 mod cv_pipeline;
 mod ui;
 use ui::InvoiceUI;
@@ -13,12 +12,12 @@ use invoice::invoice_manager::InvoiceManager;
 use std::sync::mpsc;
 use env_logger;
 use cv_worker::CVWorker;
-use constants::SourceType;
+use constants::{SourceType, PlaybackCommand, RecordingCommand, CameraCommand, CameraReport};
 use log::info;
 
 
-fn run_pipeline_thread(tx_img : mpsc::Sender<Box<egui::ColorImage>>, tx_qr : mpsc::Sender<Box<qr_code::QRCode>>, rx_focus : mpsc::Receiver<u8>, rx_source : mpsc::Receiver<SourceType>){
-    let mut cv_worker = CVWorker::create_pipeline(tx_img, tx_qr, rx_focus, rx_source);
+fn run_pipeline_thread(tx_img : mpsc::Sender<Box<egui::ColorImage>>, tx_qr : mpsc::Sender<Box<qr_code::QRCode>>, rx_focus : mpsc::Receiver<u8>, rx_source : mpsc::Receiver<SourceType>, rx_playback : mpsc::Receiver<PlaybackCommand>, rx_recording : mpsc::Receiver<RecordingCommand>, rx_camera : mpsc::Receiver<CameraCommand>, tx_camera_report : mpsc::Sender<CameraReport>){
+    let mut cv_worker = CVWorker::create_pipeline(tx_img, tx_qr, rx_focus, rx_source, rx_playback, rx_recording, rx_camera, tx_camera_report);
     cv_worker.run();
     info!("Pipeline thread exited");
 }
@@ -34,9 +33,13 @@ fn main() -> Result<(), eframe::Error> {
     let (tx_qr, rx_qr) = mpsc::channel::<Box<qr_code::QRCode>>();
     let (tx_focus, rx_focus) = mpsc::channel::<u8>();
     let (tx_source, rx_source) = mpsc::channel::<SourceType>();
+    let (tx_playback, rx_playback) = mpsc::channel::<PlaybackCommand>();
+    let (tx_recording, rx_recording) = mpsc::channel::<RecordingCommand>();
+    let (tx_camera, rx_camera) = mpsc::channel::<CameraCommand>();
+    let (tx_camera_report, rx_camera_report) = mpsc::channel::<CameraReport>();
 
     std::thread::spawn(move || {
-        run_pipeline_thread(tx_img,tx_qr, rx_focus, rx_source);
+        run_pipeline_thread(tx_img,tx_qr, rx_focus, rx_source, rx_playback, rx_recording, rx_camera, tx_camera_report);
     });
 
     let invoice_manager = InvoiceManager::new(rx_qr);
@@ -45,6 +48,9 @@ fn main() -> Result<(), eframe::Error> {
     invoice_ui.set_thread_reciever(rx_img);
     invoice_ui.set_focus_sender(tx_focus);
     invoice_ui.set_source_sender(tx_source);
+    invoice_ui.set_playback_sender(tx_playback);
+    invoice_ui.set_recording_sender(tx_recording);
+    invoice_ui.set_camera_channels(tx_camera, rx_camera_report);
 
     let options = eframe::NativeOptions {
         min_window_size: Some(egui::vec2(768.0, 640.0)),
@@ -1,11 +1,37 @@
 use opencv::prelude::*;
 use opencv::core::Rect;
+use std::fmt;
+use std::path::PathBuf;
 
 
+// File, and page or frame within it, the image holding a QR code was read from, live sources like the camera have none
+#[derive(Debug, Clone, PartialEq)]
+pub struct QRCodeOrigin {
+    pub file: PathBuf,
+    // Page of a PDF, counting from 1
+    pub page: Option<u32>,
+    // Frame of a video, counting from 0
+    pub frame: Option<u64>,
+}
+
+impl fmt::Display for QRCodeOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.file.display())?;
+        if let Some(page) = self.page {
+            write!(f, " page {}", page)?;
+        }
+        if let Some(frame) = self.frame {
+            write!(f, " frame {}", frame)?;
+        }
+        Ok(())
+    }
+}
+
 pub struct QRCode {
     image: Mat,
     data: String,
     rect: Rect,
+    origin: Option<QRCodeOrigin>,
 }
 
 
@@ -15,9 +41,18 @@ impl QRCode {
             image: image,
             data: data,
             rect: rect,
+            origin: None,
         }
     }
 
+    pub fn set_origin(&mut self, origin: Option<QRCodeOrigin>) {
+        self.origin = origin;
+    }
+
+    pub fn get_origin(&self) -> Option<&QRCodeOrigin> {
+        self.origin.as_ref()
+    }
+
     pub fn get_image(&self) -> &Mat {
         &self.image
     }
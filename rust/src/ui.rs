@@ -3,11 +3,23 @@ use egui::{ColorImage,Slider, Color32, RichText};
 use std::sync::mpsc;
 
 use super::InvoiceManager;
+use crate::invoice::invoice_manager::{InvoiceMatch, DEFAULT_MAX_CANDIDATES};
+use crate::invoice::subset_problem::search_control::SearchStopReason;
+use std::time::Duration;
+use crate::bank::StatementFormat;
+use crate::invoice::reconciliation::{MatchConfidence, ReconciliationSettings, ReconciliationStatus};
 use egui_extras::{TableBuilder, Column, StripBuilder, Size};
 use log::{debug, warn, info};
-use super::constants::SourceType;
+use super::constants::{SourceType, VideoPacing, PlaybackCommand, RecordingCommand, RecordingFormat, CameraCommand, CameraReport, CameraInfo, CameraMode, DEFAULT_PDF_DPI};
+use crate::qr_code::QRCodeOrigin;
 use std::rc::Rc;
-use crate::invoice::Invoice;
+use chrono::{Local, NaiveDate};
+use std::path::Path;
+use crate::export::{ExportFormat, EXPORT_DIRECTORY};
+use crate::invoice::{Invoice, InvoiceKey};
+use crate::invoice::subset_problem::SubsetSolverType;
+use crate::invoice::money::Money;
+use crate::invoice::match_query::MatchQuery;
 
 pub struct InvoiceUI {
     cam_texture: Option<egui::TextureHandle>,
@@ -15,9 +27,16 @@ pub struct InvoiceUI {
     image_recv: Option<mpsc::Receiver<Box<ColorImage>>>,
     focus_sender: Option<mpsc::Sender<u8>>,
     source_sender: Option<mpsc::Sender<SourceType>>,
+    playback_sender: Option<mpsc::Sender<PlaybackCommand>>,
+    recording_sender: Option<mpsc::Sender<RecordingCommand>>,
+    camera_sender: Option<mpsc::Sender<CameraCommand>>,
+    camera_report_recv: Option<mpsc::Receiver<CameraReport>>,
     inv_manager: InvoiceManager,
 
-    invoice_search_cache: Vec<Rc<dyn Invoice>>,
+    invoice_search_cache: Vec<InvoiceMatch>,
+    invoice_search_page: usize,
+    invoice_search_progress: Option<f32>,
+    invoice_search_status: Option<String>,
     invoice_search_cache_sum: f64,
     last_invoice_search_cache_sum: f64,
     find_button_active: bool,
@@ -26,8 +45,50 @@ pub struct InvoiceUI {
     last_focus_value: u8,
     source_display: SourceType,
     last_source_display: SourceType,
+    show_image_source: bool,
+    image_source_path: String,
+    image_source_watch: bool,
+    image_source_pdf_dpi: u32,
+    show_video_source: bool,
+    video_source_path: String,
+    video_looping: bool,
+    video_pacing: VideoPacing,
+    video_paused: bool,
+    show_recording: bool,
+    recording_path: String,
+    recording_format: RecordingFormat,
+    recording_active: bool,
+    show_camera: bool,
+    camera_devices: Vec<CameraInfo>,
+    camera_searching: bool,
+    camera_selected_index: i32,
+    camera_selected_mode: Option<CameraMode>,
+    camera_status: Option<String>,
 
-    highlighted_invoice_id: Option<String>,
+    highlighted_invoice_key: Option<InvoiceKey>,
+    show_rejected_scans: bool,
+    export_status: Option<String>,
+
+    show_supplier_editor: bool,
+    supplier_editor_nif: String,
+    supplier_editor_name: String,
+    supplier_editor_status: Option<String>,
+
+    show_match_constraints: bool,
+    match_tolerance: f64,
+    match_date_window_enabled: bool,
+    match_date: String,
+    match_days_before: i64,
+    match_days_after: i64,
+    match_issuer_nif: Option<String>,
+    match_max_size_enabled: bool,
+    match_max_size: usize,
+    match_must_include: Vec<InvoiceKey>,
+
+    show_reconciliation: bool,
+    statement_path: String,
+    statement_format: StatementFormat,
+    reconciliation_status: Option<String>,
 }
 
 impl InvoiceUI{
@@ -37,10 +98,17 @@ impl InvoiceUI{
             inv_manager: inv_manager,
             focus_sender: None,
             source_sender: None,
+            playback_sender: None,
+            recording_sender: None,
+            camera_sender: None,
+            camera_report_recv: None,
             last_focus_value: 0,
 
             //Cache temporary invoice table
             invoice_search_cache: Vec::new(),
+            invoice_search_page: 0,
+            invoice_search_progress: None,
+            invoice_search_status: None,
             last_invoice_search_cache_sum: 0.0,
             invoice_search_cache_sum: 0.0,
 
@@ -48,10 +116,49 @@ impl InvoiceUI{
             cam_texture: None,
             last_image: None,
             focus_value: 70,
-            highlighted_invoice_id: None,
+            highlighted_invoice_key: None,
             source_display: SourceType::Camera,
             last_source_display: SourceType::Camera,
+            show_image_source: false,
+            image_source_path: String::new(),
+            image_source_watch: false,
+            image_source_pdf_dpi: DEFAULT_PDF_DPI,
+            show_video_source: false,
+            video_source_path: String::new(),
+            video_looping: false,
+            video_pacing: VideoPacing::RealTime,
+            video_paused: false,
+            show_recording: false,
+            recording_path: String::new(),
+            recording_format: RecordingFormat::Video,
+            recording_active: false,
+            show_camera: false,
+            camera_devices: Vec::new(),
+            camera_searching: false,
+            camera_selected_index: 0,
+            camera_selected_mode: None,
+            camera_status: None,
             find_button_active: false,
+            show_rejected_scans: false,
+            export_status: None,
+            show_supplier_editor: false,
+            supplier_editor_nif: String::new(),
+            supplier_editor_name: String::new(),
+            supplier_editor_status: None,
+            show_match_constraints: false,
+            match_tolerance: 0.0,
+            match_date_window_enabled: false,
+            match_date: Local::now().format("%Y-%m-%d").to_string(),
+            match_days_before: 30,
+            match_days_after: 0,
+            match_issuer_nif: None,
+            match_max_size_enabled: false,
+            match_max_size: 5,
+            match_must_include: Vec::new(),
+            show_reconciliation: false,
+            statement_path: String::new(),
+            statement_format: StatementFormat::Csv,
+            reconciliation_status: None,
         }
     }
 
@@ -64,13 +171,22 @@ impl InvoiceUI{
         }
     }
 
+    fn describe_warnings(invoice: &dyn Invoice) -> String {
+        let warnings: Vec<String> = invoice.get_warnings().iter().map(|x| x.to_string()).collect();
+        return warnings.join("\n");
+    }
+
     fn build_invoice_table<'a>(&self, ui: &mut egui::Ui, invoice_iter: impl Iterator<Item= &'a Rc<dyn Invoice>>){
 
         TableBuilder::new(ui)
         .striped(true)
         .column(Column::initial(150.0))
         .column(Column::initial(70.0))
+        .column(Column::initial(70.0))
+        .column(Column::initial(120.0))
+        .column(Column::initial(100.0))
         .column(Column::initial(120.0))
+        .column(Column::initial(50.0))
         .min_scrolled_height(0.0)
         .header(20.0, |mut header| {
             header.col(|ui| {
@@ -79,33 +195,668 @@ impl InvoiceUI{
             header.col(|ui| {
                 ui.strong("Preço");
             });
+            header.col(|ui| {
+                ui.strong("Em aberto");
+            });
             header.col(|ui| {
                 ui.strong("Data de emissão");
             });
+            header.col(|ui| {
+                ui.strong("NIF Emitente");
+            });
+            header.col(|ui| {
+                ui.strong("Fornecedor");
+            });
+            header.col(|ui| {
+                ui.strong("Avisos");
+            });
         })
         .body(|mut body| {
             for invoice in invoice_iter {
-                let id = invoice.get_id().to_string();
 
                 let mut invoice_color = Color32::WHITE;
+                if invoice.is_annulled() {
+                    invoice_color = Color32::GRAY;
+                }
+                let invoice_key = invoice.get_key();
+                let payments: Vec<String> = self.inv_manager.get_invoice_assignments(&invoice_key)
+                    .map(|x| format!("{} {} {}€", x.movement.booking_date, x.movement.description, x.get_allocation(&invoice_key, invoice.get_signed_price()).unwrap_or_default()))
+                    .collect();
+                let open_balance = self.inv_manager.get_open_balance(invoice.as_ref());
+                if !payments.is_empty() && open_balance == Money::ZERO {
+                    invoice_color = Color32::LIGHT_BLUE;
+                }
 
-                if let Some(highlighted_invoice_id) = &self.highlighted_invoice_id {
-                    if id == *highlighted_invoice_id {
+                if let Some(highlighted_invoice_key) = &self.highlighted_invoice_key {
+                    if invoice.get_key() == *highlighted_invoice_key {
                         invoice_color = Color32::RED;
                     }
                 }
+                let mut id_hover: Vec<String> = Vec::new();
+                if let Some(origin) = self.inv_manager.get_invoice_origin(invoice.as_ref()) {
+                    id_hover.push(format!("Ficheiro: {}", InvoiceUI::describe_origin(&origin)));
+                }
+                if !payments.is_empty() {
+                    id_hover.push(format!("Pagamentos:\n{}", payments.join("\n")));
+                }
                 body.row(30.0, |mut row| {
                     row.col(|ui| {
-                        ui.label(RichText::new(invoice.get_id().to_string()).color(invoice_color));
+                        let id_label = ui.label(RichText::new(invoice.get_id().to_string()).color(invoice_color));
+                        if !id_hover.is_empty() {
+                            id_label.on_hover_text(id_hover.join("\n"));
+                        }
                     });
                     row.col(|ui| {
-                        ui.label(RichText::new(invoice.get_price().to_string()).color(invoice_color));
+                        ui.label(RichText::new(invoice.get_signed_price().to_string()).color(invoice_color));
+                    });
+                    row.col(|ui| {
+                        if !payments.is_empty() {
+                            ui.label(RichText::new(open_balance.to_string()).color(invoice_color));
+                        }
                     });
                     row.col(|ui| {
                         ui.label(RichText::new(invoice.get_emission_date().to_string()).color(invoice_color));
                     });
+                    row.col(|ui| {
+                        let nif_color = if invoice.has_nif_issues() { Color32::YELLOW } else { invoice_color };
+                        ui.label(RichText::new(invoice.get_issuer_nif()).color(nif_color));
+                    });
+                    row.col(|ui| {
+                        let supplier_name = self.inv_manager.get_supplier_name(invoice.get_issuer_nif()).unwrap_or("");
+                        ui.label(RichText::new(supplier_name).color(invoice_color));
+                    });
+                    row.col(|ui| {
+                        let warnings_count = invoice.get_warnings().len();
+                        if warnings_count > 0 {
+                            ui.label(RichText::new(format!("⚠ {}", warnings_count)).color(Color32::YELLOW))
+                            .on_hover_text(InvoiceUI::describe_warnings(invoice.as_ref()));
+                        }
+                    });
+                });
+            }
+        });
+    }
+
+    fn build_candidate_pager(&mut self, ui: &mut egui::Ui){
+        ui.horizontal(|ui| {
+            if let Some(progress) = self.invoice_search_progress {
+                ui.spinner();
+                ui.label(format!("A procurar... {:.0}%", progress * 100.0));
+                if ui.button("Cancelar").clicked() {
+                    self.inv_manager.cancel_invoice_search();
+                }
+            } else if let Some(invoice_search_status) = &self.invoice_search_status {
+                ui.label(RichText::new(invoice_search_status).color(Color32::YELLOW));
+            }
+
+            let candidates_count = self.invoice_search_cache.len();
+            if candidates_count == 0 {
+                return;
+            }
+
+            if ui.add_enabled(self.invoice_search_page > 0, egui::Button::new("◀")).clicked() {
+                self.invoice_search_page -= 1;
+            }
+            ui.label(format!("{}/{}", self.invoice_search_page + 1, candidates_count));
+            if ui.add_enabled(self.invoice_search_page + 1 < candidates_count, egui::Button::new("▶")).clicked() {
+                self.invoice_search_page += 1;
+            }
+
+            let invoice_match = &self.invoice_search_cache[self.invoice_search_page];
+            let distance_color = if invoice_match.within_tolerance { Color32::GREEN } else { Color32::YELLOW };
+            ui.label(RichText::new(format!("Total: {}€ (diferença {}€, {} dias)", invoice_match.total, invoice_match.distance, invoice_match.date_span_days)).color(distance_color));
+            if invoice_match.partial {
+                ui.label(RichText::new(format!("Pagamento parcial de {}€", invoice_match.get_allocated_total())).color(Color32::LIGHT_BLUE));
+            }
+
+            if ui.button("Marcar como pagas").clicked() {
+                self.inv_manager.cancel_invoice_search();
+                self.handle_manual_assignment();
+                return;
+            }
+
+            if candidates_count > 1 && ui.button("Escolher").clicked() {
+                // Keep only the picked alternative, a running search would replace it
+                self.inv_manager.cancel_invoice_search();
+                let picked = self.invoice_search_cache.swap_remove(self.invoice_search_page);
+                self.invoice_search_cache = vec![picked];
+                self.invoice_search_page = 0;
+            }
+        });
+    }
+
+    fn get_match_date(&self) -> Option<NaiveDate> {
+        return NaiveDate::parse_from_str(self.match_date.trim(), "%Y-%m-%d").ok();
+    }
+
+    fn build_match_query(&self) -> MatchQuery {
+        let mut query = MatchQuery::new(Money::from_f64(self.invoice_search_cache_sum))
+            .with_tolerance(Money::from_f64(self.match_tolerance))
+            .with_must_include(self.match_must_include.clone());
+
+        if self.match_date_window_enabled {
+            match self.get_match_date() {
+                Some(date) => query = query.with_date_window(date, self.match_days_before, self.match_days_after),
+                None => warn!("Ignoring date window with invalid date {}", self.match_date),
+            }
+        }
+        if let Some(issuer_nif) = &self.match_issuer_nif {
+            query = query.with_issuer_nif(issuer_nif);
+        }
+        if self.match_max_size_enabled {
+            query = query.with_max_size(self.match_max_size);
+        }
+        return query;
+    }
+
+    fn build_match_constraints_window(&mut self, ctx: &egui::Context){
+        let mut changed = false;
+        let mut show_match_constraints = self.show_match_constraints;
+
+        egui::Window::new("Restrições de procura")
+        .open(&mut show_match_constraints)
+        .default_width(400.0)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Tolerância");
+                changed |= ui.add(egui::DragValue::new(&mut self.match_tolerance).speed(0.01).clamp_range(0.0..=f64::MAX).suffix("€").min_decimals(2)).changed();
+            });
+
+            ui.horizontal(|ui| {
+                changed |= ui.checkbox(&mut self.match_date_window_enabled, "Data do movimento").changed();
+                changed |= ui.add_enabled(self.match_date_window_enabled, egui::TextEdit::singleline(&mut self.match_date).desired_width(80.0)).changed();
+                ui.label("dias antes");
+                changed |= ui.add_enabled(self.match_date_window_enabled, egui::DragValue::new(&mut self.match_days_before).clamp_range(0..=3650)).changed();
+                ui.label("dias depois");
+                changed |= ui.add_enabled(self.match_date_window_enabled, egui::DragValue::new(&mut self.match_days_after).clamp_range(0..=3650)).changed();
+            });
+            if self.match_date_window_enabled && self.get_match_date().is_none() {
+                ui.label(RichText::new("Data inválida, use AAAA-MM-DD").color(Color32::RED));
+            }
+
+            let mut issuer_nifs: Vec<String> = self.inv_manager.get_invoices().map(|x| x.get_issuer_nif().to_string()).collect();
+            issuer_nifs.sort();
+            issuer_nifs.dedup();
+            let describe_issuer = |nif: &str| match self.inv_manager.get_supplier_name(nif) {
+                Some(name) => format!("{} ({})", name, nif),
+                None => nif.to_string(),
+            };
+
+            let mut issuer_nif = self.match_issuer_nif.clone();
+            egui::ComboBox::from_label("Emitente")
+            .selected_text(issuer_nif.as_deref().map_or("Todos".to_string(), describe_issuer))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut issuer_nif, None, "Todos");
+                for nif in issuer_nifs.iter() {
+                    ui.selectable_value(&mut issuer_nif, Some(nif.clone()), describe_issuer(nif));
+                }
+            });
+            if issuer_nif != self.match_issuer_nif {
+                self.match_issuer_nif = issuer_nif;
+                changed = true;
+            }
+
+            ui.horizontal(|ui| {
+                changed |= ui.checkbox(&mut self.match_max_size_enabled, "Máximo de faturas").changed();
+                changed |= ui.add_enabled(self.match_max_size_enabled, egui::DragValue::new(&mut self.match_max_size).clamp_range(1..=100)).changed();
+            });
+
+            ui.separator();
+            ui.label("Faturas obrigatórias");
+            egui::ScrollArea::vertical().max_height(150.0).show(ui, |ui| {
+                let mut invoices: Vec<&Rc<dyn Invoice>> = self.inv_manager.get_invoices().collect();
+                invoices.sort_by_key(|x| (x.get_emission_date(), x.get_key()));
+                for invoice in invoices {
+                    let invoice_key = invoice.get_key();
+                    let mut included = self.match_must_include.contains(&invoice_key);
+                    let label = format!("{} {} {}€", invoice.get_emission_date(), invoice_key, invoice.get_signed_price());
+                    if ui.checkbox(&mut included, label).changed() {
+                        if included {
+                            self.match_must_include.push(invoice_key);
+                        } else {
+                            self.match_must_include.retain(|x| *x != invoice_key);
+                        }
+                        changed = true;
+                    }
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Tempo limite");
+                let mut search_timeout = self.inv_manager.get_search_timeout().as_secs();
+                if ui.add(egui::DragValue::new(&mut search_timeout).clamp_range(1..=3600).suffix("s")).changed() {
+                    self.inv_manager.set_search_timeout(Duration::from_secs(search_timeout));
+                }
+            });
+
+            if ui.button("Limpar restrições").clicked() {
+                self.match_tolerance = 0.0;
+                self.match_date_window_enabled = false;
+                self.match_issuer_nif = None;
+                self.match_max_size_enabled = false;
+                self.match_must_include.clear();
+                changed = true;
+            }
+        });
+
+        self.show_match_constraints = show_match_constraints;
+        if changed {
+            // Forces the next search to run again with the new constraints
+            self.last_invoice_search_cache_sum = f64::NAN;
+        }
+    }
+
+    fn get_reconciliation_settings(&self) -> ReconciliationSettings {
+        let date_window = if self.match_date_window_enabled { Some((self.match_days_before, self.match_days_after)) } else { None };
+        let max_size = if self.match_max_size_enabled { Some(self.match_max_size) } else { None };
+        return ReconciliationSettings {
+            tolerance: Money::from_f64(self.match_tolerance),
+            date_window: date_window,
+            max_size: max_size,
+            max_candidates: DEFAULT_MAX_CANDIDATES,
+        };
+    }
+
+    fn handle_statement_import(&mut self){
+        let path = Path::new(self.statement_path.trim()).to_path_buf();
+        match self.inv_manager.import_bank_statement(&path, self.statement_format) {
+            Ok(movements) => {
+                let settings = self.get_reconciliation_settings();
+                self.inv_manager.start_reconciliation(movements, settings);
+                self.reconciliation_status = Some(format!("{} movimentos a débito importados", self.inv_manager.get_reconciliation_rows().len()));
+            },
+            Err(error) => {
+                warn!("Fail to import bank statement {}", error);
+                self.reconciliation_status = Some(format!("Erro ao importar: {}", error));
+            }
+        }
+    }
+
+    fn describe_confidence(confidence: MatchConfidence) -> RichText {
+        match confidence {
+            MatchConfidence::None => RichText::new("Nenhuma").color(Color32::GRAY),
+            MatchConfidence::Low => RichText::new("Baixa").color(Color32::RED),
+            MatchConfidence::Medium => RichText::new("Média").color(Color32::YELLOW),
+            MatchConfidence::High => RichText::new("Alta").color(Color32::GREEN),
+        }
+    }
+
+    fn describe_reconciliation_status(status: ReconciliationStatus) -> &'static str {
+        match status {
+            ReconciliationStatus::Pending => "Pendente",
+            ReconciliationStatus::Searching => "A procurar",
+            ReconciliationStatus::Proposed => "Proposta",
+            ReconciliationStatus::Unmatched => "Sem correspondência",
+            ReconciliationStatus::Accepted => "Aceite",
+            ReconciliationStatus::Rejected => "Rejeitada",
+            ReconciliationStatus::Skipped => "Ignorada",
+        }
+    }
+
+    fn build_reconciliation_window(&mut self, ctx: &egui::Context){
+        let mut show_reconciliation = self.show_reconciliation;
+
+        egui::Window::new("Reconciliação bancária")
+        .open(&mut show_reconciliation)
+        .default_width(900.0)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Extrato");
+                if ui.add(egui::TextEdit::singleline(&mut self.statement_path).desired_width(300.0)).changed() {
+                    if let Some(format) = StatementFormat::from_path(Path::new(self.statement_path.trim())) {
+                        self.statement_format = format;
+                    }
+                }
+                egui::ComboBox::from_label("Formato")
+                .selected_text(self.statement_format.to_string())
+                .show_ui(ui, |ui| {
+                    for format in StatementFormat::ALL {
+                        ui.selectable_value(&mut self.statement_format, format, format.to_string());
+                    }
+                });
+                if ui.add_enabled(!self.inv_manager.is_reconciling(), egui::Button::new("Importar")).clicked() {
+                    self.handle_statement_import();
+                }
+            });
+            ui.label("Usa a tolerância, a janela de datas e o máximo de faturas das restrições de procura");
+
+            ui.horizontal(|ui| {
+                let rows = self.inv_manager.get_reconciliation_rows();
+                if self.inv_manager.is_reconciling() {
+                    let searched = rows.iter().filter(|x| x.status != ReconciliationStatus::Pending && x.status != ReconciliationStatus::Searching).count();
+                    ui.spinner();
+                    ui.label(format!("A reconciliar {}/{}", searched, rows.len()));
+                    if ui.button("Cancelar").clicked() {
+                        self.inv_manager.cancel_reconciliation();
+                    }
+                } else if let Some(reconciliation_status) = &self.reconciliation_status {
+                    ui.label(reconciliation_status);
+                }
+            });
+            ui.separator();
+
+            let mut selected_match: Option<(usize, usize)> = None;
+            let mut accepted_row: Option<usize> = None;
+            let mut rejected_row: Option<usize> = None;
+
+            TableBuilder::new(ui)
+            .striped(true)
+            .column(Column::initial(80.0))
+            .column(Column::initial(200.0))
+            .column(Column::initial(70.0))
+            .column(Column::initial(220.0))
+            .column(Column::initial(70.0))
+            .column(Column::initial(70.0))
+            .column(Column::initial(110.0))
+            .column(Column::remainder())
+            .min_scrolled_height(0.0)
+            .header(20.0, |mut header| {
+                for title in ["Data", "Descrição", "Valor", "Faturas propostas", "Total", "Confiança", "Estado", ""] {
+                    header.col(|ui| {
+                        ui.strong(title);
+                    });
+                }
+            })
+            .body(|mut body| {
+                for (row_idx, row) in self.inv_manager.get_reconciliation_rows().iter().enumerate() {
+                    body.row(25.0, |mut table_row| {
+                        table_row.col(|ui| {
+                            ui.label(row.movement.booking_date.to_string());
+                        });
+                        table_row.col(|ui| {
+                            ui.label(&row.movement.description).on_hover_text(&row.movement.reference);
+                        });
+                        table_row.col(|ui| {
+                            let amount_label = ui.label(row.movement.amount.to_string());
+                            if !row.assignment_ids.is_empty() && row.open_amount != Money::ZERO {
+                                amount_label.on_hover_text(format!("Em aberto: {}€", row.open_amount));
+                            }
+                        });
+                        table_row.col(|ui| {
+                            ui.horizontal(|ui| {
+                                if row.matches.len() > 1 {
+                                    if ui.small_button("◀").clicked() && row.selected_match > 0 {
+                                        selected_match = Some((row_idx, row.selected_match - 1));
+                                    }
+                                    ui.label(format!("{}/{}", row.selected_match + 1, row.matches.len()));
+                                    if ui.small_button("▶").clicked() {
+                                        selected_match = Some((row_idx, row.selected_match + 1));
+                                    }
+                                }
+                                if let Some(invoice_match) = row.get_selected_match() {
+                                    let ids: Vec<String> = invoice_match.invoices.iter().map(|x| x.get_key().to_string()).collect();
+                                    ui.label(ids.join(", ")).on_hover_text(ids.join("\n"));
+                                }
+                            });
+                        });
+                        table_row.col(|ui| {
+                            if let Some(invoice_match) = row.get_selected_match() {
+                                let total_label = ui.label(invoice_match.get_allocated_total().to_string());
+                                if invoice_match.partial {
+                                    total_label.on_hover_text(format!("Pagamento parcial, as faturas devem {}€", invoice_match.total));
+                                }
+                            }
+                        });
+                        table_row.col(|ui| {
+                            ui.label(InvoiceUI::describe_confidence(row.confidence));
+                        });
+                        table_row.col(|ui| {
+                            ui.label(InvoiceUI::describe_reconciliation_status(row.status));
+                        });
+                        table_row.col(|ui| {
+                            ui.horizontal(|ui| {
+                                if row.status == ReconciliationStatus::Proposed && ui.small_button("Aceitar").clicked() {
+                                    accepted_row = Some(row_idx);
+                                }
+                                if (row.status == ReconciliationStatus::Proposed || row.status == ReconciliationStatus::Accepted) && ui.small_button("Rejeitar").clicked() {
+                                    rejected_row = Some(row_idx);
+                                }
+                            });
+                        });
+                    });
+                }
+            });
+
+            if let Some((row_idx, match_idx)) = selected_match {
+                self.inv_manager.select_reconciliation_match(row_idx, match_idx);
+            }
+            if let Some(row_idx) = accepted_row {
+                if let Err(error) = self.inv_manager.accept_reconciliation_row(row_idx) {
+                    warn!("Fail to accept reconciliation {}", error);
+                    self.reconciliation_status = Some(format!("Erro ao aceitar: {}", error));
+                }
+                self.invalidate_invoice_search();
+            }
+            if let Some(row_idx) = rejected_row {
+                if let Err(error) = self.inv_manager.reject_reconciliation_row(row_idx) {
+                    warn!("Fail to reject reconciliation {}", error);
+                    self.reconciliation_status = Some(format!("Erro ao rejeitar: {}", error));
+                }
+                self.invalidate_invoice_search();
+            }
+
+            ui.separator();
+            self.build_assignments_list(ui);
+        });
+
+        self.show_reconciliation = show_reconciliation;
+    }
+
+    fn build_assignments_list(&mut self, ui: &mut egui::Ui){
+        let mut undone_assignment: Option<u64> = None;
+
+        let assignments_count = self.inv_manager.get_assignments().count();
+        egui::CollapsingHeader::new(format!("Atribuições ({})", assignments_count))
+        .show(ui, |ui| {
+            egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                for assignment in self.inv_manager.get_assignments() {
+                    ui.horizontal(|ui| {
+                        let invoices: Vec<String> = assignment.invoices.iter().map(|x| match self.inv_manager.get_invoice(x) {
+                            Some(invoice) => format!("{} ({}€)", x, assignment.get_allocation(x, invoice.get_signed_price()).unwrap_or_default()),
+                            None => x.to_string(),
+                        }).collect();
+                        ui.label(format!("{} {} {}€: {}", assignment.movement.booking_date, assignment.movement.description, assignment.movement.amount, invoices.join(", ")));
+                        if ui.small_button("Desfazer").clicked() {
+                            undone_assignment = Some(assignment.id);
+                        }
+                    });
+                }
+            });
+        });
+
+        if let Some(id) = undone_assignment {
+            if let Err(error) = self.inv_manager.undo_assignment(id) {
+                warn!("Fail to undo assignment {}", error);
+                self.reconciliation_status = Some(format!("Erro ao desfazer: {}", error));
+            }
+            self.invalidate_invoice_search();
+        }
+    }
+
+    // The invoices open for payment changed, a search for the same sum has to run again
+    fn invalidate_invoice_search(&mut self){
+        self.last_invoice_search_cache_sum = f64::NAN;
+    }
+
+    // Assigns the candidate shown in the search results to a movement typed by hand
+    fn handle_manual_assignment(&mut self){
+        let invoice_match = match self.invoice_search_cache.get(self.invoice_search_page) {
+            Some(invoice_match) => invoice_match,
+            None => return,
+        };
+        let movement = self.inv_manager.create_manual_movement(Money::from_f64(self.invoice_search_cache_sum));
+        let allocations = invoice_match.get_allocations();
+
+        match self.inv_manager.assign_invoices(&movement, &allocations) {
+            Ok(_) => {
+                self.invoice_search_cache.clear();
+                self.invoice_search_page = 0;
+                self.invoice_search_status = Some(format!("{} faturas marcadas como pagas", allocations.len()));
+                self.invalidate_invoice_search();
+            },
+            Err(error) => {
+                warn!("Fail to assign invoices {}", error);
+                self.invoice_search_status = Some(format!("Erro ao marcar como pagas: {}", error));
+            }
+        }
+    }
+
+    fn build_session_selector(&mut self, ui: &mut egui::Ui){
+        ui.horizontal(|ui| {
+            let mut session = self.inv_manager.get_session().to_string();
+            egui::ComboBox::from_label("Sessão")
+            .selected_text(session.clone())
+            .show_ui(ui, |ui| {
+                for available_session in self.inv_manager.get_sessions() {
+                    ui.selectable_value(&mut session, available_session.clone(), available_session);
+                }
+            });
+            if ui.button("Nova sessão").clicked() {
+                session = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+            }
+
+            if session != self.inv_manager.get_session() {
+                debug!("Switching to session {}", session);
+                self.inv_manager.set_session(&session);
+                self.invalidate_invoice_search();
+                self.invoice_search_cache.clear();
+                self.invoice_search_page = 0;
+                self.invoice_search_progress = None;
+                self.invoice_search_status = None;
+                self.match_must_include.clear();
+                self.highlighted_invoice_key = None;
+            }
+        });
+    }
+
+    fn handle_export(&mut self, format: ExportFormat){
+        // Session names can contain characters that are not allowed in file names
+        let session: String = self.inv_manager.get_session().chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+        let file_name = format!("faturas_{}_{}.{}", session, Local::now().format("%Y%m%d_%H%M%S"), format.get_extension());
+        let path = Path::new(EXPORT_DIRECTORY).join(file_name);
+
+        match self.inv_manager.export_invoices(&path, format) {
+            Ok(()) => {
+                info!("Invoices exported to {}", path.display());
+                self.export_status = Some(format!("Exportado para {}", path.display()));
+            },
+            Err(error) => {
+                warn!("Fail to export invoices {}", error);
+                self.export_status = Some(format!("Erro ao exportar: {}", error));
+            }
+        }
+    }
+
+    fn build_supplier_editor_window(&mut self, ctx: &egui::Context){
+        egui::Window::new("Fornecedores")
+        .open(&mut self.show_supplier_editor)
+        .default_width(400.0)
+        .show(ctx, |ui| {
+            if let Some(error) = self.inv_manager.get_name_mapping_error() {
+                ui.label(RichText::new(error).color(Color32::RED));
+                if ui.button("Recarregar").clicked() {
+                    self.inv_manager.reload_name_mapping();
+                }
+                ui.separator();
+            }
+
+            ui.horizontal(|ui| {
+                ui.label("NIF");
+                ui.add(egui::TextEdit::singleline(&mut self.supplier_editor_nif).desired_width(90.0));
+                ui.label("Nome");
+                ui.add(egui::TextEdit::singleline(&mut self.supplier_editor_name).desired_width(150.0));
+                if ui.button("Guardar").clicked() {
+                    let nif = self.supplier_editor_nif.trim().to_string();
+                    let name = self.supplier_editor_name.trim().to_string();
+                    match self.inv_manager.set_supplier_name(&nif, &name) {
+                        Ok(()) => {
+                            self.supplier_editor_status = None;
+                            self.supplier_editor_nif.clear();
+                            self.supplier_editor_name.clear();
+                        },
+                        Err(error) => {
+                            warn!("Fail to save supplier {}", error);
+                            self.supplier_editor_status = Some(error.to_string());
+                        }
+                    }
+                }
+            });
+            if let Some(status) = &self.supplier_editor_status {
+                ui.label(RichText::new(status).color(Color32::RED));
+            }
+            ui.separator();
+
+            let mut suppliers: Vec<(String, String)> = self.inv_manager.name_mapping_table().iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+            suppliers.sort_by(|a, b| a.1.cmp(&b.1));
+
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                egui::Grid::new("supplier_grid")
+                .striped(true)
+                .show(ui, |ui| {
+                    for (nif, name) in suppliers {
+                        ui.label(&nif);
+                        ui.label(&name);
+                        if ui.button("Editar").clicked() {
+                            self.supplier_editor_nif = nif.clone();
+                            self.supplier_editor_name = name.clone();
+                        }
+                        if ui.button("Remover").clicked() {
+                            if let Err(error) = self.inv_manager.remove_supplier(&nif) {
+                                warn!("Fail to remove supplier {}", error);
+                                self.supplier_editor_status = Some(error.to_string());
+                            }
+                        }
+                        ui.end_row();
+                    }
                 });
+            });
+        });
+    }
+
+    fn describe_origin(origin: &QRCodeOrigin) -> String {
+        let mut description = origin.file.display().to_string();
+        if let Some(page) = origin.page {
+            description.push_str(&format!(" (página {})", page));
+        }
+        if let Some(frame) = origin.frame {
+            description.push_str(&format!(" (fotograma {})", frame));
+        }
+        return description;
+    }
+
+    fn build_rejected_scans_window(&mut self, ctx: &egui::Context){
+        egui::Window::new("Leituras rejeitadas")
+        .open(&mut self.show_rejected_scans)
+        .default_width(600.0)
+        .show(ctx, |ui| {
+            if ui.button("Limpar").clicked() {
+                self.inv_manager.clear_rejected_scans();
             }
+            ui.separator();
+
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                egui::Grid::new("rejected_scans_grid")
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.strong("Hora");
+                    ui.strong("Campo");
+                    ui.strong("Motivo");
+                    ui.strong("Leituras");
+                    ui.strong("Ficheiro");
+                    ui.strong("Código QR");
+                    ui.end_row();
+
+                    for rejected in self.inv_manager.get_rejected_scans().iter().rev() {
+                        ui.label(rejected.last_seen.format("%H:%M:%S").to_string());
+                        ui.label(rejected.error.get_field().unwrap_or("-"));
+                        ui.label(RichText::new(rejected.error.to_string()).color(Color32::RED));
+                        ui.label(rejected.count.to_string());
+                        ui.label(rejected.origin.as_ref().map_or("-".to_string(), InvoiceUI::describe_origin));
+                        ui.label(&rejected.raw_data);
+                        ui.end_row();
+                    }
+                });
+            });
         });
     }
 
@@ -122,6 +873,19 @@ impl InvoiceUI{
         
     }
 
+    pub fn set_playback_sender(&mut self, playback_sender : mpsc::Sender<PlaybackCommand>){
+        self.playback_sender = Some(playback_sender);
+    }
+
+    pub fn set_recording_sender(&mut self, recording_sender : mpsc::Sender<RecordingCommand>){
+        self.recording_sender = Some(recording_sender);
+    }
+
+    pub fn set_camera_channels(&mut self, camera_sender : mpsc::Sender<CameraCommand>, camera_report_recv : mpsc::Receiver<CameraReport>){
+        self.camera_sender = Some(camera_sender);
+        self.camera_report_recv = Some(camera_report_recv);
+    }
+
     fn handle_focus_value(&mut self){
         if self.last_focus_value == self.focus_value{
             return;
@@ -139,15 +903,273 @@ impl InvoiceUI{
         if  self.source_display == self.last_source_display{
             return;
         }
-        
+        self.send_source();
+    }
+
+    fn send_source(&mut self){
         if let Some(source_sender) = &self.source_sender {
-            source_sender.send(self.source_display).unwrap();
+            source_sender.send(self.source_display.clone()).unwrap();
             debug!("Sent source value {:?}", self.source_display);
-            self.last_source_display = self.source_display;
+            self.last_source_display = self.source_display.clone();
+        }
+    }
+
+    // Sent even when the path did not change, so a directory can be read again
+    fn handle_image_source_open(&mut self){
+        let path = Path::new(self.image_source_path.trim()).to_path_buf();
+        if !path.exists() {
+            warn!("Image source {} does not exist", path.display());
+            return;
+        }
+        self.source_display = SourceType::ImageFiles { path: path, watch: self.image_source_watch, pdf_dpi: self.image_source_pdf_dpi };
+        self.send_source();
+    }
+
+    fn build_image_source_window(&mut self, ctx: &egui::Context){
+        let mut show_image_source = self.show_image_source;
+        egui::Window::new("Ficheiros de imagem")
+        .open(&mut show_image_source)
+        .default_width(400.0)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Ficheiro ou pasta");
+                ui.add(egui::TextEdit::singleline(&mut self.image_source_path).desired_width(300.0));
+            });
+            ui.checkbox(&mut self.image_source_watch, "Vigiar a pasta por novos ficheiros");
+            ui.horizontal(|ui| {
+                ui.label("Resolução dos PDF");
+                ui.add(egui::DragValue::new(&mut self.image_source_pdf_dpi).clamp_range(72..=600).suffix(" DPI"));
+            });
+            let path_exists = Path::new(self.image_source_path.trim()).exists();
+            if !self.image_source_path.trim().is_empty() && !path_exists {
+                ui.label(RichText::new("O caminho não existe").color(Color32::RED));
+            }
+            if ui.add_enabled(path_exists, egui::Button::new("Abrir")).clicked() {
+                self.handle_image_source_open();
+            }
+        });
+        self.show_image_source = show_image_source;
+    }
+
+    fn handle_video_source_open(&mut self){
+        let path = Path::new(self.video_source_path.trim()).to_path_buf();
+        if !path.is_file() {
+            warn!("Video source {} does not exist", path.display());
+            return;
+        }
+        self.video_paused = false;
+        self.source_display = SourceType::VideoFile { path: path, looping: self.video_looping, pacing: self.video_pacing };
+        self.send_source();
+    }
+
+    fn send_playback(&mut self, command: PlaybackCommand){
+        if let Some(playback_sender) = &self.playback_sender {
+            playback_sender.send(command).unwrap();
+            debug!("Sent playback command {:?}", command);
+        }
+    }
+
+    fn build_video_source_window(&mut self, ctx: &egui::Context){
+        let mut show_video_source = self.show_video_source;
+        let video_playing = matches!(self.last_source_display, SourceType::VideoFile { .. });
+        let last_looping = self.video_looping;
+        let last_pacing = self.video_pacing;
+        egui::Window::new("Vídeo")
+        .open(&mut show_video_source)
+        .default_width(400.0)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Ficheiro");
+                ui.add(egui::TextEdit::singleline(&mut self.video_source_path).desired_width(300.0));
+            });
+            ui.checkbox(&mut self.video_looping, "Repetir");
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut self.video_pacing, VideoPacing::RealTime, "Tempo real");
+                ui.radio_value(&mut self.video_pacing, VideoPacing::AsFastAsPossible, "Máxima velocidade");
+            });
+            let path_exists = Path::new(self.video_source_path.trim()).is_file();
+            if !self.video_source_path.trim().is_empty() && !path_exists {
+                ui.label(RichText::new("O ficheiro não existe").color(Color32::RED));
+            }
+            if ui.add_enabled(path_exists, egui::Button::new("Abrir")).clicked() {
+                self.handle_video_source_open();
+            }
+
+            if video_playing {
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui.button(if self.video_paused { "Continuar" } else { "Pausa" }).clicked() {
+                        self.video_paused = !self.video_paused;
+                        self.send_playback(if self.video_paused { PlaybackCommand::Pause } else { PlaybackCommand::Resume });
+                    }
+                    if ui.add_enabled(self.video_paused, egui::Button::new("Avançar fotograma")).clicked() {
+                        self.send_playback(PlaybackCommand::Step);
+                    }
+                });
+            }
+        });
+        self.show_video_source = show_video_source;
+
+        // Playing video follows the options without being opened again
+        if video_playing && self.video_looping != last_looping {
+            self.send_playback(PlaybackCommand::SetLooping(self.video_looping));
+        }
+        if video_playing && self.video_pacing != last_pacing {
+            self.send_playback(PlaybackCommand::SetPacing(self.video_pacing));
+        }
+    }
+
+    fn send_recording(&mut self, command: RecordingCommand){
+        if let Some(recording_sender) = &self.recording_sender {
+            recording_sender.send(command.clone()).unwrap();
+            debug!("Sent recording command {:?}", command);
+        }
+    }
+
+    fn build_recording_window(&mut self, ctx: &egui::Context){
+        let mut show_recording = self.show_recording;
+        egui::Window::new("Gravação")
+        .open(&mut show_recording)
+        .default_width(400.0)
+        .show(ctx, |ui| {
+            ui.add_enabled_ui(!self.recording_active, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Pasta");
+                    ui.add(egui::TextEdit::singleline(&mut self.recording_path).desired_width(300.0));
+                });
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut self.recording_format, RecordingFormat::Video, "Vídeo");
+                    ui.radio_value(&mut self.recording_format, RecordingFormat::ImageSequence, "Sequência de imagens");
+                });
+            });
+            let path_exists = Path::new(self.recording_path.trim()).is_dir();
+            if !self.recording_path.trim().is_empty() && !path_exists {
+                ui.label(RichText::new("A pasta não existe").color(Color32::RED));
+            }
+
+            if self.recording_active {
+                ui.label(RichText::new("A gravar").color(Color32::RED));
+                if ui.button("Parar").clicked() {
+                    self.recording_active = false;
+                    self.send_recording(RecordingCommand::Stop);
+                }
+            } else if ui.add_enabled(path_exists, egui::Button::new("Gravar")).clicked() {
+                self.recording_active = true;
+                let path = Path::new(self.recording_path.trim()).to_path_buf();
+                self.send_recording(RecordingCommand::Start { path: path, format: self.recording_format });
+            }
+        });
+        self.show_recording = show_recording;
+    }
+
+    fn send_camera(&mut self, command: CameraCommand){
+        if let Some(camera_sender) = &self.camera_sender {
+            camera_sender.send(command.clone()).unwrap();
+            debug!("Sent camera command {:?}", command);
+        }
+    }
+
+    fn handle_camera_reports(&mut self){
+        let reports: Vec<CameraReport> = match &self.camera_report_recv {
+            Some(camera_report_recv) => camera_report_recv.try_iter().collect(),
+            None => return,
+        };
+        for report in reports {
+            match report {
+                CameraReport::Devices(devices) => {
+                    self.camera_searching = false;
+                    self.camera_devices = devices;
+                },
+                CameraReport::Opened { index, settings } => {
+                    self.camera_selected_index = index;
+                    self.camera_status = Some(format!("Câmara {}: {}", index, settings));
+                },
+                CameraReport::Failed(error) => {
+                    self.camera_status = Some(format!("Erro: {}", error));
+                },
+            }
         }
     }
 
+    fn build_camera_window(&mut self, ctx: &egui::Context){
+        let mut show_camera = self.show_camera;
+        egui::Window::new("Câmara")
+        .open(&mut show_camera)
+        .default_width(400.0)
+        .show(ctx, |ui| {
+            if let Some(camera_status) = &self.camera_status {
+                ui.label(camera_status);
+            }
+            ui.horizontal(|ui| {
+                if ui.add_enabled(!self.camera_searching, egui::Button::new("Procurar câmaras")).clicked() {
+                    self.camera_searching = true;
+                    self.send_camera(CameraCommand::Enumerate);
+                }
+                if self.camera_searching {
+                    ui.spinner();
+                }
+            });
+            if self.camera_devices.is_empty() {
+                return;
+            }
+
+            let mut selected_index = self.camera_selected_index;
+            egui::ComboBox::from_label("Dispositivo")
+            .selected_text(format!("Câmara {}", selected_index))
+            .show_ui(ui, |ui| {
+                for device in self.camera_devices.iter() {
+                    ui.selectable_value(&mut selected_index, device.index, format!("Câmara {}", device.index));
+                }
+            });
+            if selected_index != self.camera_selected_index {
+                self.camera_selected_index = selected_index;
+                self.camera_selected_mode = None;
+            }
+
+            let modes = self.camera_devices.iter()
+                .find(|x| x.index == self.camera_selected_index)
+                .map_or(Vec::new(), |x| x.modes.clone());
+            egui::ComboBox::from_label("Modo")
+            .selected_text(self.camera_selected_mode.as_ref().map_or("Automático".to_string(), |x| x.to_string()))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.camera_selected_mode, None, "Automático");
+                for mode in modes.iter() {
+                    ui.selectable_value(&mut self.camera_selected_mode, Some(mode.clone()), mode.to_string());
+                }
+            });
+
+            if ui.button("Abrir").clicked() {
+                self.source_display = SourceType::Camera;
+                self.last_source_display = SourceType::Camera;
+                self.send_camera(CameraCommand::Select { index: self.camera_selected_index, mode: self.camera_selected_mode.clone() });
+            }
+        });
+        self.show_camera = show_camera;
+    }
+
     fn handle_invoice_search(&mut self){
+        if let Some(update) = self.inv_manager.check_invoice_search() {
+            self.invoice_search_cache = update.matches;
+            self.invoice_search_page = self.invoice_search_page.min(self.invoice_search_cache.len().saturating_sub(1));
+
+            if update.finished {
+                info!("Found {} candidate subsets", self.invoice_search_cache.len());
+                self.invoice_search_progress = None;
+                self.invoice_search_status = update.stop_reason.map(|x| match x {
+                    SearchStopReason::Cancelled => "Procura cancelada, resultados parciais".to_string(),
+                    SearchStopReason::DeadlineReached => "Tempo limite atingido, resultados parciais".to_string(),
+                });
+                if update.approximate && self.inv_manager.get_solver_type() == SubsetSolverType::Exact {
+                    let approximate_status = "Demasiadas faturas para a procura exata, resultados aproximados";
+                    self.invoice_search_status = Some(match &self.invoice_search_status {
+                        Some(status) => format!("{}; {}", status, approximate_status),
+                        None => approximate_status.to_string(),
+                    });
+                }
+            } else {
+                self.invoice_search_progress = Some(update.progress);
+            }
+        }
 
         if !self.find_button_active{
             return;
@@ -157,15 +1179,25 @@ impl InvoiceUI{
             return;
         }
         debug!("Searching for invoice with sum {}", self.invoice_search_cache_sum);
-        self.invoice_search_cache = self.inv_manager.get_best_invoice_match(self.invoice_search_cache_sum);
-        info!("Found {} invoices", self.invoice_search_cache.len());
+        let query = self.build_match_query();
+        self.invoice_search_cache.clear();
+        self.invoice_search_page = 0;
+        if self.inv_manager.start_invoice_search(&query, DEFAULT_MAX_CANDIDATES) {
+            self.invoice_search_progress = Some(0.0);
+            self.invoice_search_status = None;
+        } else {
+            self.invoice_search_progress = None;
+            self.invoice_search_status = Some("As restrições não podem ser cumpridas".to_string());
+        }
         self.last_invoice_search_cache_sum = self.invoice_search_cache_sum;
     }
 
     fn ui_controller(&mut self) {
         self.handle_source();
         self.handle_focus_value();
+        self.handle_camera_reports();
         self.handle_invoice_search();
+        self.inv_manager.check_reconciliation();
     }
 
 }
@@ -175,15 +1207,19 @@ impl eframe::App for InvoiceUI {
         self.ui_controller();
         egui::CentralPanel::default().show(ctx, |ui| {
             self.update_last_image();
+            let invoice_count = self.inv_manager.get_invoice_count();
             let qr_result = self.inv_manager.check_qr_channel();
+            if self.inv_manager.get_invoice_count() != invoice_count {
+                self.invalidate_invoice_search();
+            }
             
             match qr_result {
                 Ok(invoice) => {
 
                     if let Some(invoice) = invoice{
-                        let invoice_id = invoice.get_id();
-                        debug!("Found invoice with id {}", invoice_id);
-                        self.highlighted_invoice_id = Some(invoice_id.to_string());
+                        let invoice_key = invoice.get_key();
+                        debug!("Found invoice with key {}", invoice_key);
+                        self.highlighted_invoice_key = Some(invoice_key);
                     }
                 },
                 Err(error) => {
@@ -224,12 +1260,21 @@ impl eframe::App for InvoiceUI {
                                 strip.cell(|ui|{
                                     ui.add(Slider::new(&mut self.focus_value, 0..=255).step_by(5.0).text("Focus"));
                                 });
-                                strip.cell(|_|{
+                                strip.cell(|ui|{
+                                    self.build_session_selector(ui);
                                 });
                                 strip.cell(|ui|{
                                     ui.horizontal(|ui| {
                                         ui.radio_value(&mut self.source_display, SourceType::Camera, "Camera");
                                         ui.radio_value(&mut self.source_display, SourceType::Display, "Ecrã");
+                                        let image_files_selected = matches!(self.source_display, SourceType::ImageFiles { .. });
+                                        if ui.radio(image_files_selected, "Ficheiros").clicked() {
+                                            self.show_image_source = true;
+                                        }
+                                        let video_file_selected = matches!(self.source_display, SourceType::VideoFile { .. });
+                                        if ui.radio(video_file_selected, "Vídeo").clicked() {
+                                            self.show_video_source = true;
+                                        }
                                     });
                                 });
                             });
@@ -261,10 +1306,15 @@ impl eframe::App for InvoiceUI {
                             ui.push_id(2, |ui| {
                                 StripBuilder::new(ui)
                                 .size(Size::remainder().at_least(80.0)) // for the table
-                                .size(Size::exact(10.0)) // for the source code link
+                                .size(Size::exact(25.0)) // for the candidate pager
                                 .vertical(|mut strip| {
                                     strip.cell(|ui| {
-                                        self.build_invoice_table(ui, self.invoice_search_cache.iter());
+                                        if let Some(invoice_match) = self.invoice_search_cache.get(self.invoice_search_page) {
+                                            self.build_invoice_table(ui, invoice_match.invoices.iter());
+                                        }
+                                    });
+                                    strip.cell(|ui| {
+                                        self.build_candidate_pager(ui);
                                     });
                                 });
                             });
@@ -280,11 +1330,70 @@ impl eframe::App for InvoiceUI {
                             }else{
                                 self.find_button_active = false;
                             }
+
+                            if ui.button("Restrições").clicked() {
+                                self.show_match_constraints = !self.show_match_constraints;
+                            }
+
+                            let mut solver_type = self.inv_manager.get_solver_type();
+                            ui.radio_value(&mut solver_type, SubsetSolverType::Exact, "Exato");
+                            ui.radio_value(&mut solver_type, SubsetSolverType::Greedy, "Aproximado");
+                            if solver_type != self.inv_manager.get_solver_type() {
+                                self.inv_manager.set_solver_type(solver_type);
+                                self.last_invoice_search_cache_sum = f64::NAN;
+                            }
+
+                            let mut include_annulled = self.inv_manager.get_include_annulled();
+                            if ui.checkbox(&mut include_annulled, "Incluir anuladas").changed() {
+                                self.inv_manager.set_include_annulled(include_annulled);
+                                // Forces the next search to run again with the new filter
+                                self.last_invoice_search_cache_sum = f64::NAN;
+                            }
+                            ui.label(format!("Total: {}€", self.inv_manager.get_total()));
+
+                            let rejected_count = self.inv_manager.get_rejected_scans().len();
+                            if ui.button(format!("Leituras rejeitadas ({})", rejected_count)).clicked() {
+                                self.show_rejected_scans = !self.show_rejected_scans;
+                            }
+
+                            if ui.button("Reconciliação").clicked() {
+                                self.show_reconciliation = !self.show_reconciliation;
+                            }
+
+                            if ui.button("Câmara").clicked() {
+                                self.show_camera = !self.show_camera;
+                            }
+
+                            if ui.button("Gravação").clicked() {
+                                self.show_recording = !self.show_recording;
+                            }
+
+                            if ui.button("Fornecedores").clicked() {
+                                self.show_supplier_editor = !self.show_supplier_editor;
+                            }
+
+                            if ui.button("Exportar CSV").clicked() {
+                                self.handle_export(ExportFormat::Csv);
+                            }
+                            if ui.button("Exportar Excel").clicked() {
+                                self.handle_export(ExportFormat::Xlsx);
+                            }
+                            if let Some(export_status) = &self.export_status {
+                                ui.label(export_status);
+                            }
                         });
                     });
                 });
             });
             ctx.request_repaint();
         });
+        self.build_rejected_scans_window(ctx);
+        self.build_supplier_editor_window(ctx);
+        self.build_match_constraints_window(ctx);
+        self.build_reconciliation_window(ctx);
+        self.build_image_source_window(ctx);
+        self.build_video_source_window(ctx);
+        self.build_recording_window(ctx);
+        self.build_camera_window(ctx);
     }
 }
\ No newline at end of file